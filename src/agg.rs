@@ -0,0 +1,432 @@
+use std::error::Error;
+
+use arrow::array::{RecordBatch, UInt32Array};
+use arrow::compute::{SortOptions, take_record_batch};
+use arrow::datatypes::SchemaRef;
+use arrow::row::{RowConverter, Rows, SortField};
+use futures::TryStreamExt;
+
+use crate::aggregate::{AggFunc, Aggregate, HashAggregation};
+use crate::dataset::{DatasetFiles, open_dataset};
+use crate::encryption::Keys;
+use crate::pqt::value_to_string;
+use crate::predicate::Predicate;
+use crate::spill::{MAX_PARTITIONS, MAX_SPILL_LEVEL, Partitions};
+use crate::topn::TopRows;
+use crate::utils::{compute_col_max_len, human_size, print_rows};
+
+pub const DEFAULT_AGG_MEMORY_MB: usize = 256;
+pub const DEFAULT_AGG_LIMIT: usize = 20;
+
+#[derive(Clone, Debug)]
+pub struct AggOptions {
+    // group columns, all rows make one group when empty
+    pub by: Vec<String>,
+    pub aggregates: Vec<Aggregate>,
+    pub filter: Option<Predicate>,
+    // groups printed, the largest first
+    pub limit: usize,
+    // bytes the groups may take before rows of new groups are spilled to disk
+    pub memory: usize,
+}
+
+// The aggregates computed: those asked for, and count(*) to sort the groups by when
+// it isn't one of them
+fn counted(options: &AggOptions) -> Vec<Aggregate> {
+    let mut aggregates = options.aggregates.clone();
+    if !aggregates.contains(&COUNT) {
+        aggregates.push(COUNT);
+    }
+    aggregates
+}
+
+const COUNT: Aggregate = Aggregate {
+    func: AggFunc::Count,
+    column: None,
+};
+
+// The columns to read for the aggregation
+fn input_columns(options: &AggOptions) -> Vec<String> {
+    let mut columns = options.by.clone();
+    for column in options.aggregates.iter().filter_map(|a| a.column.as_ref()) {
+        if !columns.contains(column) {
+            columns.push(column.clone());
+        }
+    }
+    columns
+}
+
+// Aggregate the rows of the dataset by the group columns. Groups are folded in a hash
+// table until it outgrows the memory cap; from then on only the rows of groups already
+// in it are, and the rows of other groups are partitioned by group to disk and
+// aggregated a partition at a time once all rows are read, see finish. Only the groups
+// printed are kept of each.
+pub(crate) async fn parquet_agg(
+    opener: &dyn DatasetFiles,
+    paths: Vec<String>,
+    keys: &Keys,
+    options: &AggOptions,
+) -> Result<(), Box<dyn Error>> {
+    let (result, total, rows) = aggregate(opener, paths, keys, options).await?;
+    print_groups(&result, total);
+    println!(
+        "{} {} of {} rows",
+        total,
+        if total == 1 { "group" } else { "groups" },
+        rows
+    );
+    Ok(())
+}
+
+// The top groups, with the number of groups and of rows aggregated
+async fn aggregate(
+    opener: &dyn DatasetFiles,
+    paths: Vec<String>,
+    keys: &Keys,
+    options: &AggOptions,
+) -> Result<(RecordBatch, usize, usize), Box<dyn Error>> {
+    let dataset = open_dataset(
+        opener,
+        paths,
+        keys,
+        Some(&input_columns(options)),
+        options.filter.as_ref(),
+        options.filter.is_some(),
+    )
+    .await?;
+    // Masked values can't be grouped or summed
+    if let Some(file) = dataset.files.iter().find(|file| !file.masked.is_empty()) {
+        return Err(format!(
+            "{}: no key to decrypt {}",
+            file.path,
+            file.masked.join(", ")
+        )
+        .into());
+    }
+    let schema = &dataset.schema;
+    let estimate = dataset
+        .files
+        .iter()
+        .flat_map(|file| file.metadata.metadata().row_groups())
+        .map(|rg| rg.total_byte_size().max(0) as usize)
+        .sum();
+
+    let mut aggregation = HashAggregation::new(options.by.clone(), counted(options));
+    // Sets up the result types even when no row matches
+    aggregation.update(&RecordBatch::new_empty(schema.clone()))?;
+    let mut spilled: Option<Partitions> = None;
+    let mut rows = 0;
+    for file in dataset.files.iter() {
+        let builder = dataset.reader(file).await?;
+        let mut stream = builder.with_batch_size(8192).build()?;
+        while let Some(batch) = stream.try_next().await? {
+            let batch = file.conform(&batch, schema)?;
+            rows += batch.num_rows();
+            fold(&mut aggregation, &mut spilled, &batch, estimate, 0, options)?;
+        }
+    }
+
+    let mut top = TopGroups::new(options);
+    finish(aggregation, spilled, &mut top, options)?;
+    let total = top.groups;
+    Ok((top.finish()?, total, rows))
+}
+
+// Fold a batch into the aggregation. Once its groups outgrow the memory cap, only rows
+// of the groups in it are, and the rows of new groups are spilled to partitions at the
+// level, sized by the estimate of the bytes to come.
+fn fold(
+    aggregation: &mut HashAggregation,
+    spilled: &mut Option<Partitions>,
+    batch: &RecordBatch,
+    estimate: usize,
+    level: usize,
+    options: &AggOptions,
+) -> Result<(), Box<dyn Error>> {
+    let Some(partitions) = spilled else {
+        aggregation.update(batch)?;
+        if aggregation.memory_size() > options.memory {
+            // sized so that the groups of a partition fit in memory
+            let count = estimate.div_ceil(options.memory).clamp(2, MAX_PARTITIONS);
+            // partitions spilling again aren't reported, there may be many of them
+            if level == 0 {
+                println!(
+                    "{} groups don't fit in {}, spilling rows of new groups to {} files",
+                    aggregation.num_groups(),
+                    human_size(options.memory as i64),
+                    count
+                );
+            }
+            *spilled = Some(Partitions::new(count, level));
+        }
+        return Ok(());
+    };
+    let left_out = aggregation.update_known(batch)?;
+    if !left_out.is_empty() {
+        let batch = take_record_batch(batch, &UInt32Array::from(left_out))?;
+        partitions.write(&batch, &aggregation.key_rows(&batch)?)?;
+    }
+    Ok(())
+}
+
+// Add the groups of the aggregation to the top ones, then aggregate the partitions it
+// spilled one at a time. The groups of a partition that outgrow the memory cap spill
+// again, at the next level, up to MAX_SPILL_LEVEL; past that the partition's groups
+// are held whole, so the cap is best-effort.
+fn finish(
+    aggregation: HashAggregation,
+    spilled: Option<Partitions>,
+    top: &mut TopGroups,
+    options: &AggOptions,
+) -> Result<(), Box<dyn Error>> {
+    top.push(aggregation.finish()?)?;
+    let Some(mut partitions) = spilled else {
+        return Ok(());
+    };
+    let level = partitions.level() + 1;
+    for idx in 0..partitions.len() {
+        let estimate = partitions.bytes(idx);
+        let Some(reader) = partitions.take(idx)? else {
+            continue;
+        };
+        let mut aggregation = HashAggregation::new(options.by.clone(), counted(options));
+        let mut spilled = None;
+        for batch in reader {
+            let batch = batch?;
+            if level > MAX_SPILL_LEVEL {
+                aggregation.update(&batch)?;
+            } else {
+                fold(
+                    &mut aggregation,
+                    &mut spilled,
+                    &batch,
+                    estimate,
+                    level,
+                    options,
+                )?;
+            }
+        }
+        finish(aggregation, spilled, top, options)?;
+    }
+    Ok(())
+}
+
+// The groups that sort first, kept as the groups of the aggregation and its spilled
+// partitions are added, along with a count of all groups
+struct TopGroups<'a> {
+    options: &'a AggOptions,
+    top: TopRows,
+    schema: Option<SchemaRef>,
+    added: usize,
+    groups: usize,
+}
+
+impl<'a> TopGroups<'a> {
+    fn new(options: &'a AggOptions) -> Self {
+        Self {
+            options,
+            top: TopRows::new(options.limit),
+            schema: None,
+            added: 0,
+            groups: 0,
+        }
+    }
+
+    fn push(&mut self, batch: RecordBatch) -> Result<(), Box<dyn Error>> {
+        self.groups += batch.num_rows();
+        self.schema.get_or_insert_with(|| batch.schema());
+        let keys = sort_keys(&batch, self.options)?;
+        self.top.push(&batch, &keys, self.added, 0)?;
+        self.added += 1;
+        Ok(())
+    }
+
+    // The top groups, without count(*) when it was only computed to sort by
+    fn finish(self) -> Result<RecordBatch, Box<dyn Error>> {
+        let schema = self.schema.ok_or("No rows to aggregate were read")?;
+        let batch = self
+            .top
+            .finish()?
+            .unwrap_or_else(|| RecordBatch::new_empty(schema));
+        if self.options.aggregates.contains(&COUNT) {
+            return Ok(batch);
+        }
+        Ok(batch.project(&(0..batch.num_columns() - 1).collect::<Vec<_>>())?)
+    }
+}
+
+// The largest groups first, then in the order of the group columns, so the top
+// groups are the same however the groups were spilled
+fn sort_keys(batch: &RecordBatch, options: &AggOptions) -> Result<Rows, Box<dyn Error>> {
+    let count = batch
+        .column_by_name(&COUNT.name())
+        .ok_or("count(*) is always computed")?;
+    let mut fields = vec![SortField::new_with_options(
+        count.data_type().clone(),
+        SortOptions {
+            descending: true,
+            nulls_first: false,
+        },
+    )];
+    let mut columns = vec![count.clone()];
+    for idx in 0..options.by.len() {
+        fields.push(SortField::new(batch.column(idx).data_type().clone()));
+        columns.push(batch.column(idx).clone());
+    }
+    Ok(RowConverter::new(fields)?.convert_columns(&columns)?)
+}
+
+fn print_groups(batch: &RecordBatch, total: usize) {
+    let schema = batch.schema();
+    let mut rows = vec![
+        schema
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect::<Vec<String>>(),
+    ];
+    for i in 0..batch.num_rows() {
+        rows.push(
+            batch
+                .columns()
+                .iter()
+                .map(|column| value_to_string(column.as_ref(), i))
+                .collect(),
+        );
+    }
+    let col_max_len = compute_col_max_len(&rows);
+    print_rows(&rows, &col_max_len, true);
+    if total > batch.num_rows() {
+        println!("... {} more groups", total - batch.num_rows());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use arrow::array::{AsArray, Int64Array};
+    use arrow::datatypes::{DataType, Field, Int64Type, Schema};
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+
+    use super::*;
+    use crate::pqt::reader_from_bytes;
+    use crate::provider::ParquetSource;
+
+    // Files kept in memory by path
+    struct Files(HashMap<String, Vec<u8>>);
+
+    #[async_trait::async_trait]
+    impl DatasetFiles for Files {
+        async fn open(&self, path: &str) -> Result<ParquetSource, Box<dyn Error>> {
+            Ok(ParquetSource {
+                filename: path.to_string(),
+                reader: reader_from_bytes(self.0[path].clone()),
+                size: None,
+            })
+        }
+    }
+
+    // Two files of 1000 rows each, grouped by g into 97 groups, in row groups of 100 rows
+    fn files() -> Files {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("g", DataType::Int64, false),
+            Field::new("v", DataType::Int64, false),
+        ]));
+        let mut files = HashMap::new();
+        for (path, start) in [("a.parquet", 0), ("b.parquet", 1000)] {
+            let rows: Vec<i64> = (start..start + 1000).collect();
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from_iter_values(
+                        rows.iter().map(|i| i * 31 % 97),
+                    )),
+                    Arc::new(Int64Array::from(rows)),
+                ],
+            )
+            .unwrap();
+            let properties = WriterProperties::builder()
+                .set_max_row_group_size(100)
+                .build();
+            let mut content = vec![];
+            let mut writer =
+                ArrowWriter::try_new(&mut content, schema.clone(), Some(properties)).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+            files.insert(path.to_string(), content);
+        }
+        Files(files)
+    }
+
+    // Aggregate the files with the groups held in memory, and again with a memory
+    // cap of a byte, which spills every new group's rows and every partition again
+    // up to MAX_SPILL_LEVEL
+    async fn aggregate_both_ways(aggregates: Vec<Aggregate>) -> RecordBatch {
+        let files = files();
+        let paths = vec!["a.parquet".to_string(), "b.parquet".to_string()];
+        let mut options = AggOptions {
+            by: vec!["g".to_string()],
+            aggregates,
+            filter: None,
+            limit: 10,
+            memory: DEFAULT_AGG_MEMORY_MB << 20,
+        };
+        let keys = Keys::default();
+        let (in_memory, groups, rows) = aggregate(&files, paths.clone(), &keys, &options)
+            .await
+            .unwrap();
+        assert_eq!((groups, rows), (97, 2000));
+        options.memory = 1;
+        let (spilled, groups, rows) = aggregate(&files, paths, &keys, &options).await.unwrap();
+        assert_eq!((groups, rows), (97, 2000));
+        assert_eq!(spilled, in_memory);
+        in_memory
+    }
+
+    fn int_column(batch: &RecordBatch, name: &str) -> Vec<i64> {
+        batch
+            .column_by_name(name)
+            .unwrap()
+            .as_primitive::<Int64Type>()
+            .values()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn spilled_groups_match_in_memory() {
+        let result = aggregate_both_ways(vec![
+            COUNT,
+            Aggregate {
+                func: AggFunc::Sum,
+                column: Some("v".to_string()),
+            },
+        ])
+        .await;
+        // 2000 rows make 60 groups of 21 rows and 37 of 20, the largest are listed
+        // first and then by the lowest g
+        assert_eq!(int_column(&result, "count(*)"), vec![21; 10]);
+        let mut largest: Vec<i64> = (0..60).map(|i| i * 31 % 97).collect();
+        largest.sort();
+        assert_eq!(int_column(&result, "g"), largest[..10]);
+        let sums: Vec<i64> = largest[..10]
+            .iter()
+            .map(|g| (0..2000).filter(|i| i * 31 % 97 == *g).sum())
+            .collect();
+        assert_eq!(int_column(&result, "sum(v)"), sums);
+    }
+
+    #[tokio::test]
+    async fn groups_are_ordered_by_count_when_it_isnt_shown() {
+        let with_count = aggregate_both_ways(vec![COUNT]).await;
+        let without = aggregate_both_ways(vec![Aggregate {
+            func: AggFunc::Max,
+            column: Some("v".to_string()),
+        }])
+        .await;
+        assert_eq!(without.num_columns(), 2);
+        assert_eq!(int_column(&without, "g"), int_column(&with_count, "g"));
+    }
+}
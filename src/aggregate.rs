@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, AsArray, Float64Array, Int64Array, RecordBatch, new_null_array,
+};
+use arrow::compute::{CastOptions, cast, cast_with_options};
+use arrow::datatypes::{DataType, Field, Float64Type, Int64Type, Schema};
+use arrow::row::{OwnedRow, RowConverter, Rows, SortField};
+use arrow::util::display::FormatOptions;
+
+use crate::predicate::column_by_path;
+
+// Casts that fail rather than turn values that don't fit into nulls
+const STRICT: CastOptions = CastOptions {
+    safe: false,
+    format_options: FormatOptions::new(),
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggFunc {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "count" => Some(AggFunc::Count),
+            "sum" => Some(AggFunc::Sum),
+            "avg" => Some(AggFunc::Avg),
+            "min" => Some(AggFunc::Min),
+            "max" => Some(AggFunc::Max),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Avg => "avg",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+        }
+    }
+}
+
+// An aggregate function over a column, the column is None for count(*)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregate {
+    pub func: AggFunc,
+    pub column: Option<String>,
+}
+
+impl Aggregate {
+    // Name of the result column, e.g. sum(amount)
+    pub fn name(&self) -> String {
+        format!(
+            "{}({})",
+            self.func.name(),
+            self.column.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+// Groups rows by the values of the group columns and folds every group into the
+// aggregates. The group keys are kept in Arrow's row format, so any column type can
+// be grouped on and the result keeps the original types.
+pub struct HashAggregation {
+    group_by: Vec<String>,
+    aggregates: Vec<Aggregate>,
+    // set up from the column types of the first batch
+    state: Option<State>,
+}
+
+struct State {
+    keys: RowConverter,
+    key_fields: Vec<Field>,
+    inputs: Vec<InputType>,
+    // groups by the bytes of their keys, which rows are looked up by without a copy
+    groups: HashMap<Box<[u8]>, usize>,
+    group_keys: Vec<OwnedRow>,
+    // accumulators[group][aggregate]
+    accumulators: Vec<Vec<Accumulator>>,
+    // rough size of the groups, kept up as groups are added and min and max change
+    bytes: usize,
+}
+
+// How an aggregate reads its column
+enum InputType {
+    Rows,
+    Int,
+    Float,
+    // min and max compare values in the row format, whatever the type
+    Ordered {
+        data_type: DataType,
+        converter: RowConverter,
+        null: OwnedRow,
+    },
+}
+
+enum Input {
+    Rows,
+    Valid(ArrayRef),
+    Int(ArrayRef),
+    Float(ArrayRef),
+    Ordered(Rows, ArrayRef),
+}
+
+#[derive(Clone)]
+enum Accumulator {
+    Count(i64),
+    SumInt(Option<i64>),
+    SumFloat(Option<f64>),
+    Avg(f64, i64),
+    Min(Option<OwnedRow>),
+    Max(Option<OwnedRow>),
+}
+
+impl HashAggregation {
+    pub fn new(group_by: Vec<String>, aggregates: Vec<Aggregate>) -> Self {
+        Self {
+            group_by,
+            aggregates,
+            state: None,
+        }
+    }
+
+    pub fn num_groups(&self) -> usize {
+        match &self.state {
+            Some(state) if self.group_by.is_empty() => state.accumulators.len(),
+            Some(state) => state.group_keys.len(),
+            None => 0,
+        }
+    }
+
+    // Rough size of the groups held, to tell when they no longer fit in memory
+    pub fn memory_size(&self) -> usize {
+        self.state.as_ref().map_or(0, |state| state.bytes)
+    }
+
+    pub fn update(&mut self, batch: &RecordBatch) -> Result<(), Box<dyn Error>> {
+        self.fold(batch, true).map(|_| ())
+    }
+
+    // Fold only the rows of groups seen before. The rows that would start a new group
+    // are left out, their indices are returned.
+    pub fn update_known(&mut self, batch: &RecordBatch) -> Result<Vec<u32>, Box<dyn Error>> {
+        self.fold(batch, false)
+    }
+
+    // The group keys of the rows in Arrow's row format, the same for rows of a group
+    pub fn key_rows(&self, batch: &RecordBatch) -> Result<Rows, Box<dyn Error>> {
+        let state = self
+            .state
+            .as_ref()
+            .ok_or("No rows to aggregate were read")?;
+        let key_columns = self
+            .group_by
+            .iter()
+            .zip(state.key_fields.iter())
+            .map(|(path, field)| Ok(cast(&column_by_path(batch, path)?, field.data_type())?))
+            .collect::<Result<Vec<ArrayRef>, Box<dyn Error>>>()?;
+        Ok(state.keys.convert_columns(&key_columns)?)
+    }
+
+    fn fold(&mut self, batch: &RecordBatch, new_groups: bool) -> Result<Vec<u32>, Box<dyn Error>> {
+        if self.state.is_none() {
+            self.state = Some(self.init(batch)?);
+        }
+        let key_rows = self.key_rows(batch)?;
+        let state = self.state.as_mut().expect("state is set up above");
+
+        let inputs = self
+            .aggregates
+            .iter()
+            .zip(state.inputs.iter())
+            .map(|(aggregate, input)| read_input(batch, aggregate, input))
+            .collect::<Result<Vec<Input>, Box<dyn Error>>>()?;
+
+        let mut left_out = vec![];
+        for i in 0..batch.num_rows() {
+            let group = if self.group_by.is_empty() {
+                0
+            } else {
+                let key = key_rows.row(i);
+                match state.groups.get(key.as_ref()) {
+                    Some(group) => *group,
+                    None if !new_groups => {
+                        left_out.push(i as u32);
+                        continue;
+                    }
+                    None => {
+                        let group = state.group_keys.len();
+                        // the key is held twice, in the map and in the list of groups
+                        state.bytes += 2 * key.as_ref().len()
+                            + self.aggregates.len() * std::mem::size_of::<Accumulator>()
+                            + 2 * std::mem::size_of::<OwnedRow>()
+                            + std::mem::size_of::<usize>();
+                        state.groups.insert(key.as_ref().into(), group);
+                        state.group_keys.push(key.owned());
+                        state
+                            .accumulators
+                            .push(new_accumulators(&self.aggregates, &state.inputs));
+                        group
+                    }
+                }
+            };
+            for ((accumulator, input), aggregate) in state.accumulators[group]
+                .iter_mut()
+                .zip(inputs.iter())
+                .zip(self.aggregates.iter())
+            {
+                let held = accumulator.heap_size();
+                if !accumulator.update(input, i) {
+                    return Err(format!("{} overflows a 64-bit integer", aggregate.name()).into());
+                }
+                state.bytes = state.bytes + accumulator.heap_size() - held;
+            }
+        }
+        Ok(left_out)
+    }
+
+    fn init(&self, batch: &RecordBatch) -> Result<State, Box<dyn Error>> {
+        let mut key_fields = vec![];
+        for path in self.group_by.iter() {
+            let array = column_by_path(batch, path)?;
+            key_fields.push(Field::new(path, array.data_type().clone(), true));
+        }
+        let keys = RowConverter::new(
+            key_fields
+                .iter()
+                .map(|f| SortField::new(f.data_type().clone()))
+                .collect(),
+        )?;
+
+        let mut inputs = vec![];
+        for aggregate in self.aggregates.iter() {
+            let Some(column) = &aggregate.column else {
+                inputs.push(InputType::Rows);
+                continue;
+            };
+            let data_type = column_by_path(batch, column)?.data_type().clone();
+            inputs.push(match aggregate.func {
+                AggFunc::Count => InputType::Rows,
+                AggFunc::Sum | AggFunc::Avg if !data_type.is_numeric() => {
+                    return Err(format!("{} needs a numeric column", aggregate.name()).into());
+                }
+                AggFunc::Sum if data_type.is_integer() => InputType::Int,
+                AggFunc::Sum | AggFunc::Avg => InputType::Float,
+                AggFunc::Min | AggFunc::Max => {
+                    let converter = RowConverter::new(vec![SortField::new(data_type.clone())])?;
+                    let null = converter
+                        .convert_columns(&[new_null_array(&data_type, 1)])?
+                        .row(0)
+                        .owned();
+                    InputType::Ordered {
+                        data_type,
+                        converter,
+                        null,
+                    }
+                }
+            });
+        }
+
+        // Without groups all rows fold into one, which exists even when there are no rows
+        let accumulators = if self.group_by.is_empty() {
+            vec![new_accumulators(&self.aggregates, &inputs)]
+        } else {
+            vec![]
+        };
+        Ok(State {
+            keys,
+            key_fields,
+            inputs,
+            groups: HashMap::new(),
+            group_keys: vec![],
+            accumulators,
+            bytes: 0,
+        })
+    }
+
+    // One row per group: the group columns named by their paths, then the aggregates
+    // named like sum(amount)
+    pub fn finish(self) -> Result<RecordBatch, Box<dyn Error>> {
+        let Some(state) = self.state else {
+            return Err("No rows to aggregate were read".into());
+        };
+        let mut fields = state.key_fields.clone();
+        let mut columns = state
+            .keys
+            .convert_rows(state.group_keys.iter().map(|k| k.row()))?;
+
+        for (idx, (aggregate, input)) in self.aggregates.iter().zip(state.inputs.iter()).enumerate()
+        {
+            let values = state.accumulators.iter().map(|group| &group[idx]);
+            let column: ArrayRef = match input {
+                InputType::Ordered {
+                    converter, null, ..
+                } => {
+                    let rows = values.map(|acc| match acc {
+                        Accumulator::Min(Some(row)) | Accumulator::Max(Some(row)) => row.row(),
+                        _ => null.row(),
+                    });
+                    converter.convert_rows(rows)?.remove(0)
+                }
+                InputType::Rows | InputType::Int => {
+                    Arc::new(values.map(Accumulator::int).collect::<Int64Array>())
+                }
+                InputType::Float => {
+                    Arc::new(values.map(Accumulator::float).collect::<Float64Array>())
+                }
+            };
+            fields.push(Field::new(
+                aggregate.name(),
+                column.data_type().clone(),
+                true,
+            ));
+            columns.push(column);
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
+
+fn read_input(
+    batch: &RecordBatch,
+    aggregate: &Aggregate,
+    input: &InputType,
+) -> Result<Input, Box<dyn Error>> {
+    let Some(column) = &aggregate.column else {
+        return Ok(Input::Rows);
+    };
+    let array = column_by_path(batch, column)?;
+    Ok(match input {
+        InputType::Rows => Input::Valid(array),
+        // unsigned values past i64::MAX are an error, not nulls left out of the sum
+        InputType::Int => Input::Int(
+            cast_with_options(&array, &DataType::Int64, &STRICT)
+                .map_err(|_| format!("{} overflows a 64-bit integer", aggregate.name()))?,
+        ),
+        InputType::Float => Input::Float(cast(&array, &DataType::Float64)?),
+        InputType::Ordered {
+            data_type,
+            converter,
+            ..
+        } => {
+            let array = cast(&array, data_type)?;
+            Input::Ordered(
+                converter.convert_columns(std::slice::from_ref(&array))?,
+                array,
+            )
+        }
+    })
+}
+
+fn new_accumulators(aggregates: &[Aggregate], inputs: &[InputType]) -> Vec<Accumulator> {
+    aggregates
+        .iter()
+        .zip(inputs)
+        .map(|(aggregate, input)| match aggregate.func {
+            AggFunc::Count => Accumulator::Count(0),
+            AggFunc::Sum if matches!(input, InputType::Int) => Accumulator::SumInt(None),
+            AggFunc::Sum => Accumulator::SumFloat(None),
+            AggFunc::Avg => Accumulator::Avg(0.0, 0),
+            AggFunc::Min => Accumulator::Min(None),
+            AggFunc::Max => Accumulator::Max(None),
+        })
+        .collect()
+}
+
+impl Accumulator {
+    // Fold in the value of row i, false when an integer sum no longer fits
+    fn update(&mut self, input: &Input, i: usize) -> bool {
+        // Only count(*) counts nulls
+        if let Input::Valid(array)
+        | Input::Int(array)
+        | Input::Float(array)
+        | Input::Ordered(_, array) = input
+            && array.is_null(i)
+        {
+            return true;
+        }
+        match (self, input) {
+            (Accumulator::Count(n), Input::Rows | Input::Valid(_)) => *n += 1,
+            (Accumulator::SumInt(sum), Input::Int(array)) => {
+                let value = array.as_primitive::<Int64Type>().value(i);
+                let Some(total) = sum.unwrap_or(0).checked_add(value) else {
+                    return false;
+                };
+                *sum = Some(total);
+            }
+            (Accumulator::SumFloat(sum), Input::Float(array)) => {
+                let value = array.as_primitive::<Float64Type>().value(i);
+                *sum = Some(sum.unwrap_or(0.0) + value);
+            }
+            (Accumulator::Avg(sum, count), Input::Float(array)) => {
+                *sum += array.as_primitive::<Float64Type>().value(i);
+                *count += 1;
+            }
+            (Accumulator::Min(min), Input::Ordered(rows, _))
+                if min.as_ref().is_none_or(|m| rows.row(i) < m.row()) =>
+            {
+                *min = Some(rows.row(i).owned());
+            }
+            (Accumulator::Max(max), Input::Ordered(rows, _))
+                if max.as_ref().is_none_or(|m| rows.row(i) > m.row()) =>
+            {
+                *max = Some(rows.row(i).owned());
+            }
+            _ => {}
+        }
+        true
+    }
+
+    // Bytes held outside the accumulator, by the values of min and max
+    fn heap_size(&self) -> usize {
+        match self {
+            Accumulator::Min(Some(row)) | Accumulator::Max(Some(row)) => row.row().as_ref().len(),
+            _ => 0,
+        }
+    }
+
+    fn int(&self) -> Option<i64> {
+        match self {
+            Accumulator::Count(n) => Some(*n),
+            Accumulator::SumInt(sum) => *sum,
+            _ => None,
+        }
+    }
+
+    fn float(&self) -> Option<f64> {
+        match self {
+            Accumulator::SumFloat(sum) => *sum,
+            Accumulator::Avg(sum, count) if *count > 0 => Some(sum / *count as f64),
+            _ => None,
+        }
+    }
+}
@@ -1,14 +1,19 @@
 use std::error::Error;
 
+use crate::encryption::{KeyedReader, Keys};
+use crate::predicate::Predicate;
 use crate::{
-    pqt,
-    provider::{fs::LocalFs, http::PublicHttpEndpoint},
+    agg, dataset, diff, grep, pages, pqt, profile,
+    provider::{ParquetSource, fs::LocalFs, http::PublicHttpEndpoint},
+    sample, schemas, sql, utils, verify,
 };
 
 pub struct FileBrowser {
     endpoint: String,
     http: PublicHttpEndpoint,
     localfs: LocalFs,
+    // keys of encrypted files, used for every file opened
+    pub(crate) keys: Keys,
 }
 
 impl FileBrowser {
@@ -19,11 +24,12 @@ impl FileBrowser {
             endpoint,
             http,
             localfs,
+            keys: Keys::default(),
         })
     }
 
     fn get_full_path(&self, path: &str) -> String {
-        if path.len() == 0 {
+        if path.is_empty() {
             return self.endpoint.clone();
         }
         if self.endpoint.ends_with('/') {
@@ -82,7 +88,11 @@ impl FileBrowser {
     // }
 
     fn get_provider(&self) -> &dyn crate::provider::Provider {
-        if self.endpoint.starts_with("http://") || self.endpoint.starts_with("https://") {
+        self.provider_for(&self.endpoint)
+    }
+
+    fn provider_for(&self, endpoint: &str) -> &dyn crate::provider::Provider {
+        if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
             &self.http
         } else {
             &self.localfs
@@ -98,7 +108,482 @@ impl FileBrowser {
         }
     }
 
-    pub(crate) async fn view(&self, path: String, max_rows: usize) -> Result<(), Box<dyn Error>> {
+    // Open Parquet files relative to the current endpoint, inside a ZIP the path is
+    // matched as a prefix of the entry names
+    pub(crate) async fn open_parquet(
+        &self,
+        path: &str,
+    ) -> Result<Vec<ParquetSource>, Box<dyn Error>> {
+        self.open_parquet_at(&self.endpoint, path).await
+    }
+
+    async fn open_parquet_at(
+        &self,
+        endpoint: &str,
+        path: &str,
+    ) -> Result<Vec<ParquetSource>, Box<dyn Error>> {
+        let provider = self.provider_for(endpoint);
+        let sources = if endpoint.ends_with("zip") {
+            let sources = provider.open_parquet_from_zip(endpoint, path).await?;
+            sources
+                .into_iter()
+                .filter(|s| s.filename == path || s.filename.ends_with(".parquet"))
+                .collect()
+        } else {
+            let url = if endpoint.ends_with('/') {
+                format!("{}{}", endpoint, path)
+            } else {
+                format!("{}/{}", endpoint, path)
+            };
+            vec![provider.open_parquet(&url).await?]
+        };
+        sources
+            .into_iter()
+            .map(|source| {
+                Ok(ParquetSource {
+                    filename: source.filename,
+                    size: source.size,
+                    reader: Box::new(KeyedReader::new(source.reader, source.size, &self.keys)?),
+                })
+            })
+            .collect()
+    }
+
+    // A single file, relative to the current endpoint or at a location of its own: an
+    // absolute path or URL, which may lead into a ZIP, e.g. /exports/day.zip/part-0.parquet
+    pub(crate) async fn open_location(&self, path: &str) -> Result<ParquetSource, Box<dyn Error>> {
+        let absolute =
+            path.starts_with('/') || path.starts_with("http://") || path.starts_with("https://");
+        let into_zip = path.contains(".zip/") && !self.endpoint.ends_with("zip");
+        let sources = if !absolute && !into_zip {
+            self.open_parquet(path).await?
+        } else {
+            let location = if absolute {
+                path.to_string()
+            } else {
+                self.get_full_path(path)
+            };
+            match location.split_once(".zip/") {
+                Some((zip, entry)) => self.open_parquet_at(&format!("{}.zip", zip), entry).await?,
+                None => {
+                    let (dir, file) = location.rsplit_once('/').unwrap_or(("", &location));
+                    self.open_parquet_at(&format!("{}/", dir), file).await?
+                }
+            }
+        };
+        sources
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("No Parquet file at {}", path).into())
+    }
+
+    // Load a key file, a relative path is taken from the current directory when that is
+    // a local one
+    pub(crate) fn load_keys(&mut self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let path = if std::path::Path::new(&self.endpoint).is_dir() {
+            self.get_full_path(path)
+        } else {
+            path.to_string()
+        };
+        self.keys.load(&path)
+    }
+
+    // Parquet files matching a glob pattern, relative to the current directory or
+    // among the entries of the current ZIP
+    pub(crate) async fn find_parquet(&self, pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self
+            .find_files(pattern)
+            .await?
+            .into_iter()
+            .filter(|name| name.ends_with(".parquet"))
+            .collect())
+    }
+
+    async fn find_files(&self, pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let provider = self.get_provider();
+        let files = if self.endpoint.ends_with("zip") {
+            let glob = glob::Pattern::new(pattern)?;
+            let options = glob::MatchOptions {
+                require_literal_separator: true,
+                ..Default::default()
+            };
+            let entries = provider.list_zip(&self.endpoint, "").await?;
+            entries
+                .into_iter()
+                .filter_map(|row| row.into_iter().next())
+                .filter(|name| glob.matches_with(name, options))
+                .collect()
+        } else {
+            provider.find(&self.endpoint, pattern).await?
+        };
+        Ok(files)
+    }
+
+    // Row counts of the matching files from their footers, or by scanning the
+    // filter columns when a filter is given
+    pub(crate) async fn count(
+        &self,
+        pattern: &str,
+        filter: Option<&Predicate>,
+    ) -> Result<(), Box<dyn Error>> {
+        let files = self.find_parquet(pattern).await?;
+        if files.is_empty() {
+            return Err(format!("No Parquet files match {}", pattern).into());
+        }
+
+        let mut rows = vec![vec![
+            "File".to_string(),
+            "Row groups".to_string(),
+            "Rows".to_string(),
+        ]];
+        if filter.is_some() {
+            rows[0].push("Matching rows".to_string());
+        }
+        let (mut total_rows, mut total_matching) = (0, 0);
+        for file in files {
+            for source in self.open_parquet(&file).await? {
+                let count = match pqt::parquet_count(source.reader, filter).await {
+                    Ok(count) => count,
+                    Err(e) => {
+                        rows.push(vec![file.clone(), format!("Error: {}", e)]);
+                        continue;
+                    }
+                };
+                total_rows += count.rows;
+                let mut row = vec![
+                    file.clone(),
+                    count.row_groups.to_string(),
+                    count.rows.to_string(),
+                ];
+                if let Some(matching) = count.matching {
+                    total_matching += matching;
+                    row.push(matching.to_string());
+                }
+                rows.push(row);
+            }
+        }
+        let mut total = vec![
+            format!("Total ({} files)", rows.len() - 1),
+            String::new(),
+            total_rows.to_string(),
+        ];
+        if filter.is_some() {
+            total.push(total_matching.to_string());
+        }
+        rows.push(total);
+
+        let col_max_len = utils::compute_col_max_len(&rows);
+        utils::print_rows(&rows, &col_max_len, true);
+        Ok(())
+    }
+
+    // Run a query over the files its FROM clause matches
+    pub(crate) async fn sql(
+        &self,
+        query: &str,
+        options: &pqt::ViewOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let query = sql::Query::parse(query)?;
+        let files = self.find_parquet(&query.from).await?;
+        if files.is_empty() {
+            return Err(format!("No Parquet files match {}", query.from).into());
+        }
+        let mut sources = vec![];
+        for file in files {
+            sources.extend(self.open_parquet(&file).await?);
+        }
+        let (result, rows) = query.execute(sources, options.max_rows).await?;
+        let shown = result.num_rows();
+        let options = pqt::ViewOptions {
+            max_rows: shown,
+            ..options.clone()
+        };
+        pqt::print_batches(&[result], &options);
+        if rows > shown {
+            println!("... {} more rows, add LIMIT to see more", rows - shown);
+        }
+        println!("({} {})", rows, if rows == 1 { "row" } else { "rows" });
+        Ok(())
+    }
+
+    pub(crate) async fn schema(&self, path: String) -> Result<(), Box<dyn Error>> {
+        for source in self.open_parquet(&path).await? {
+            println!("Schema of Parquet file: {}", source.filename);
+            pqt::parquet_schema(source.reader).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn schemadiff(&self, a: &str, b: &str) -> Result<(), Box<dyn Error>> {
+        let a = self.open_location(a).await?;
+        let b = self.open_location(b).await?;
+        println!("Comparing schemas of {} and {}", a.filename, b.filename);
+        schemas::parquet_schemadiff((&a.filename, a.reader), (&b.filename, b.reader)).await
+    }
+
+    pub(crate) async fn schemas(&self, pattern: &str) -> Result<(), Box<dyn Error>> {
+        let files = self.find_parquet(pattern).await?;
+        if files.is_empty() {
+            return Err(format!("No Parquet files match {}", pattern).into());
+        }
+        let mut versions = schemas::SchemaVersions::new();
+        for file in files {
+            match self.open_parquet(&file).await {
+                Ok(sources) => {
+                    for source in sources {
+                        versions.add(source.filename, source.reader).await;
+                    }
+                }
+                Err(e) => versions.unreadable(file, e.as_ref()),
+            }
+        }
+        versions.print();
+        Ok(())
+    }
+
+    // Compare the rows of two files, each of which may come from anywhere
+    pub(crate) async fn diff(
+        &self,
+        a: &str,
+        b: &str,
+        options: &diff::DiffOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let a = self.open_location(a).await?;
+        let b = self.open_location(b).await?;
+        println!("Comparing a: {}", a.filename);
+        println!("     with b: {}", b.filename);
+        println!("by key {}", options.key.join(", "));
+        diff::parquet_diff(a, b, options).await
+    }
+
+    // A single file, or the files below a directory or matching a glob, by the paths
+    // their partition values are parsed from. They're opened when the dataset is read.
+    async fn dataset_paths(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        if path.ends_with(".parquet") {
+            return Ok(vec![path.to_string()]);
+        }
+        let pattern = dataset::dataset_pattern(path);
+        let files = self.find_parquet(&pattern).await?;
+        if files.is_empty() {
+            return Err(format!("No Parquet files match {}", pattern).into());
+        }
+        Ok(files)
+    }
+
+    // Rows picked at random from a file, or from the files below a directory or
+    // matching a glob
+    pub(crate) async fn sample(
+        &self,
+        path: &str,
+        count: usize,
+        seed: u64,
+        options: &pqt::ViewOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        println!("Sampling {} rows of {}", count, path);
+        let paths = self.dataset_paths(path).await?;
+        let dataset = dataset::open_dataset(self, paths, &self.keys, None, None, true).await?;
+        sample::parquet_sample(dataset, count, seed, options).await
+    }
+
+    // Search the values of the files matching a glob for a pattern, including the
+    // Parquet files inside matching ZIP archives
+    pub(crate) async fn grep(
+        &self,
+        pattern: &str,
+        options: &grep::GrepOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut grep = grep::Grep::new(options.clone());
+        if !pattern.contains(['*', '?', '[']) && pattern.ends_with(".parquet") {
+            let source = self.open_location(pattern).await?;
+            grep.search(pattern, source.reader).await?;
+            grep.print();
+            return Ok(());
+        }
+        // a directory stands for everything below it
+        let pattern = if pattern.contains(['*', '?', '[']) || pattern.ends_with(".zip") {
+            pattern.to_string()
+        } else {
+            format!("{}/**/*", pattern.trim_end_matches('/'))
+        };
+        let files = self.find_files(&pattern).await?;
+        if !files
+            .iter()
+            .any(|f| f.ends_with(".parquet") || f.ends_with(".zip"))
+        {
+            return Err(format!("No Parquet or ZIP files match {}", pattern).into());
+        }
+        for file in files {
+            if grep.is_done() {
+                break;
+            }
+            let sources = if file.ends_with(".parquet") {
+                self.open_parquet(&file).await
+            } else if file.ends_with(".zip") && !self.endpoint.ends_with("zip") {
+                self.open_parquet_at(&self.get_full_path(&file), "").await
+            } else {
+                continue;
+            };
+            let sources = match sources {
+                Ok(sources) => sources,
+                Err(e) => {
+                    println!("{}: Error: {}", file, e);
+                    continue;
+                }
+            };
+            for source in sources {
+                if grep.is_done() {
+                    break;
+                }
+                let name = if file.ends_with(".zip") {
+                    format!("{}/{}", file, source.filename)
+                } else {
+                    file.clone()
+                };
+                if let Err(e) = grep.search(&name, source.reader).await {
+                    println!("{}: Error: {}", name, e);
+                }
+            }
+        }
+        grep.print();
+        Ok(())
+    }
+
+    // Grouped aggregates of a file, or of the files below a directory or matching a glob
+    pub(crate) async fn agg(
+        &self,
+        path: &str,
+        options: &agg::AggOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        println!("Aggregating {}", path);
+        let paths = self.dataset_paths(path).await?;
+        agg::parquet_agg(self, paths, &self.keys, options).await
+    }
+
+    pub(crate) async fn meta(&self, path: String, detail: bool) -> Result<(), Box<dyn Error>> {
+        for source in self.open_parquet(&path).await? {
+            println!("Metadata of Parquet file: {}", source.filename);
+            pqt::parquet_meta(source.reader, detail).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn stats(
+        &self,
+        path: String,
+        columns: Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        for source in self.open_parquet(&path).await? {
+            println!("Statistics of Parquet file: {}", source.filename);
+            pqt::parquet_stats(source.reader, &columns).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn profile(
+        &self,
+        path: String,
+        columns: Vec<String>,
+        sample: Option<usize>,
+    ) -> Result<(), Box<dyn Error>> {
+        for source in self.open_parquet(&path).await? {
+            println!("Profile of Parquet file: {}", source.filename);
+            profile::parquet_profile(source.reader, &columns, sample).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn bloom(
+        &self,
+        path: String,
+        column: &str,
+        value: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        for source in self.open_parquet(&path).await? {
+            println!("Parquet file: {}", source.filename);
+            pqt::parquet_bloom(source.reader, column, value).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn pages(&self, path: String, column: &str) -> Result<(), Box<dyn Error>> {
+        for source in self.open_parquet(&path).await? {
+            println!("Pages of Parquet file: {}", source.filename);
+            pages::parquet_pages(source.reader, column).await?;
+        }
+        Ok(())
+    }
+
+    // Check every matching file from its footer down to the values of every page,
+    // listing the problems found and summing each file up in a line
+    pub(crate) async fn verify(&self, pattern: &str) -> Result<(), Box<dyn Error>> {
+        let files = self.find_parquet(pattern).await?;
+        if files.is_empty() {
+            return Err(format!("No Parquet files match {}", pattern).into());
+        }
+        let (mut verified, mut with_problems) = (0, 0);
+        for file in files {
+            let sources = match self.open_parquet(&file).await {
+                Ok(sources) => sources,
+                Err(e) => {
+                    println!("{}: Error: {}", file, e);
+                    with_problems += 1;
+                    continue;
+                }
+            };
+            for source in sources {
+                println!("Verifying Parquet file: {}", source.filename);
+                let verification = verify::parquet_verify(source.reader).await;
+                verification.print_problems();
+                println!("{}: {}", source.filename, verification.summary());
+                verified += 1;
+                if !verification.problems.is_empty() {
+                    with_problems += 1;
+                }
+            }
+        }
+        println!(
+            "Verified {} files, {} with problems",
+            verified, with_problems
+        );
+        Ok(())
+    }
+
+    // View the Parquet files below a directory, or matching a glob, as one table
+    async fn view_dataset(
+        &self,
+        path: &str,
+        options: &pqt::ViewOptions,
+    ) -> Result<pqt::ViewPage, Box<dyn Error>> {
+        let pattern = dataset::dataset_pattern(path);
+        let files = self.find_parquet(&pattern).await?;
+        if files.is_empty() {
+            return Err(format!("No Parquet files match {}", pattern).into());
+        }
+        println!("Viewing dataset: {}", path);
+        dataset::dataset_view(self, files, options).await
+    }
+
+    pub(crate) async fn view(
+        &self,
+        path: String,
+        options: &pqt::ViewOptions,
+    ) -> Result<Option<pqt::ViewPage>, Box<dyn Error>> {
+        let options = &pqt::ViewOptions {
+            keys: self.keys.clone(),
+            ..options.clone()
+        };
+        if options.dataset {
+            return self.view_dataset(&path, options).await.map(Some);
+        }
+        let mut page = None;
+        if path.ends_with(".parquet") {
+            for source in self.open_parquet(&path).await? {
+                println!("Viewing Parquet file: {}", source.filename);
+                page = Some(pqt::parquet_view(source.reader, options).await?);
+            }
+            return Ok(page);
+        }
+
         let provider = self.get_provider();
         let url = self.get_full_path(&path);
         let files = if self.endpoint.ends_with("zip") {
@@ -107,16 +592,26 @@ impl FileBrowser {
             provider.get_file(&url).await
         }?;
 
-        for file in files.iter() {
+        for file in files.into_iter() {
             if file.filename.ends_with(".parquet") {
-                println!("Viewing Parquet file:");
-                pqt::parquet_view_from_slice(&file.content, max_rows)?;
+                println!("Viewing Parquet file: {}", file.filename);
+                let size = Some(file.content.len() as u64);
+                let reader =
+                    KeyedReader::new(pqt::reader_from_bytes(file.content), size, &self.keys)?;
+                page = Some(pqt::parquet_view(reader, options).await?);
             } else {
                 let readable_content = String::from_utf8_lossy(&file.content);
                 println!("File contents:\n{}", readable_content);
             }
         }
 
-        Ok(())
+        Ok(page)
+    }
+}
+
+#[async_trait::async_trait]
+impl dataset::DatasetFiles for FileBrowser {
+    async fn open(&self, path: &str) -> Result<ParquetSource, Box<dyn Error>> {
+        self.open_location(path).await
     }
 }
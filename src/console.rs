@@ -1,16 +1,62 @@
-use rustyline::Editor;
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+
+use rustyline::completion::Completer;
+use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{ColorMode, Editor, Helper};
 
-use crate::{browser, utils};
+use crate::aggregate::{AggFunc, Aggregate};
+use crate::predicate::Predicate;
+use crate::topn::OrderBy;
+use crate::{agg, browser, diff, encryption, grep, pqt, utils};
 
 pub struct Console {
     browser: browser::FileBrowser,
+    // the last viewed Parquet file, moved through by next, prev and goto
+    cursor: Option<Cursor>,
+    // how view lays out rows unless told otherwise, switched by \x
+    display: pqt::DisplayMode,
+    terminal_width: Option<usize>,
+}
+
+struct Cursor {
+    path: String,
+    options: pqt::ViewOptions,
+    page: pqt::ViewPage,
 }
 
 impl Console {
     pub fn new(endpoint: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let browser = browser::FileBrowser::new(endpoint.to_owned())?;
-        Ok(Self { browser })
+        Ok(Self {
+            browser,
+            cursor: None,
+            display: pqt::DisplayMode::Auto,
+            terminal_width: None,
+        })
+    }
+
+    // View a file and remember where it ended for next, prev and goto
+    async fn view(&mut self, path: &str, options: pqt::ViewOptions) {
+        let mut shown = options.clone();
+        shown.display = Some(options.display.unwrap_or(self.display));
+        shown.terminal_width = self.terminal_width;
+        match self.browser.view(path.to_owned(), &shown).await {
+            Ok(Some(page)) => {
+                self.cursor = Some(Cursor {
+                    path: path.to_owned(),
+                    options,
+                    page,
+                })
+            }
+            Ok(None) => self.cursor = None,
+            Err(e) => println!("Error viewing file {}: {}", path, e),
+        }
     }
 
     pub async fn process_console_input(&mut self) {
@@ -22,15 +68,18 @@ impl Console {
 
         loop {
             let readline = rl.readline(">> ");
+            self.terminal_width = rl.dimensions().map(|(width, _)| width as usize);
             match readline {
                 Ok(line) => {
                     let input = line.trim();
-                    if !input.is_empty() {
+                    // keys typed with the key command stay out of the history
+                    if !input.is_empty() && !input.starts_with("key ") {
                         let _ = rl.add_history_entry(input);
                         history.push(input.to_string());
                     }
 
-                    let args: Vec<&str> = input.split_whitespace().collect();
+                    let args = utils::split_args(input);
+                    let args: Vec<&str> = args.iter().map(String::as_str).collect();
                     if args.is_empty() {
                         continue;
                     }
@@ -61,28 +110,395 @@ impl Console {
                             self.browser.get_current_dir()
                         }),
                         "view" => {
+                            // `view --dataset <dir-or-glob>` reads like the flag belongs first
+                            let (dataset, args) = match args.get(1) {
+                                Some(&"--dataset") => (true, [&args[..1], &args[2..]].concat()),
+                                _ => (false, args.clone()),
+                            };
                             if args.len() > 1 {
                                 let path = args[1];
-                                let max_rows = if args.len() > 2 {
-                                    if let Ok(num) = args[2].parse() {
-                                        num
-                                    } else {
-                                        20
+                                let mut options = match parse_view_options(&args[2..]) {
+                                    Ok(options) => options,
+                                    Err(e) => {
+                                        println!("Error: {}", e);
+                                        continue;
                                     }
-                                } else {
-                                    20
                                 };
+                                options.dataset |= dataset;
 
+                                self.view(path, options).await;
+                            } else {
+                                println!(
+                                    "Usage: view <file> [rows] [--offset N] [--limit M] [--row-group 3|1-3|0,2] [--columns a,b,...] [--nested [col=]flatten|json|compact,...] [-x|--table] [--where <filter>] [--order-by col [desc],...]"
+                                );
+                                println!(
+                                    "       view --dataset <dir-or-glob> [options] - View the files as one table with Hive partition columns"
+                                );
+                            }
+                        }
+                        "next" | "prev" | "goto" => {
+                            let Some(cursor) = &self.cursor else {
+                                println!("No Parquet file viewed yet");
+                                continue;
+                            };
+                            let mut options = cursor.options.clone();
+                            let page = cursor.page;
+                            match args[0] {
+                                "next" => {
+                                    let at_end = match page.total {
+                                        Some(total) => page.offset + page.rows >= total,
+                                        None => page.rows < options.max_rows,
+                                    };
+                                    if at_end {
+                                        println!("Already at the end of {}", cursor.path);
+                                        continue;
+                                    }
+                                    options.offset += options.max_rows;
+                                }
+                                "prev" => {
+                                    if page.offset == 0 {
+                                        println!("Already at the start of {}", cursor.path);
+                                        continue;
+                                    }
+                                    options.offset = page.offset.saturating_sub(options.max_rows);
+                                }
+                                _ => match args.get(1).and_then(|row| row.parse::<usize>().ok()) {
+                                    Some(row) if row > 0 => options.offset = row - 1,
+                                    _ => {
+                                        println!("Usage: goto <row>, counting rows from 1");
+                                        continue;
+                                    }
+                                },
+                            }
+                            let path = cursor.path.clone();
+                            self.view(&path, options).await;
+                        }
+                        "count" => {
+                            if args.len() > 1 && (args.len() == 2 || args[2] == "--where") {
+                                let pattern = args[1];
+                                let filter = if args.len() > 2 {
+                                    match Predicate::parse(&args[3..].join(" ")) {
+                                        Ok(filter) => Some(filter),
+                                        Err(e) => {
+                                            println!("Error: {}", e);
+                                            continue;
+                                        }
+                                    }
+                                } else {
+                                    None
+                                };
+                                self.browser
+                                    .count(pattern, filter.as_ref())
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error counting rows of {}: {}", pattern, e);
+                                    });
+                            } else {
+                                println!("Usage: count <glob> [--where <filter>]");
+                            }
+                        }
+                        "sql" => {
+                            let query = input.split_once(char::is_whitespace).map(|(_, q)| q);
+                            if let Some(query) = query {
+                                let options = pqt::ViewOptions {
+                                    display: Some(self.display),
+                                    terminal_width: self.terminal_width,
+                                    ..Default::default()
+                                };
+                                self.browser.sql(query, &options).await.unwrap_or_else(|e| {
+                                    println!("Error running query: {}", e);
+                                });
+                            } else {
+                                println!(
+                                    "Usage: sql SELECT ... FROM '<glob>' [WHERE ...] [GROUP BY ...] [ORDER BY ...] [LIMIT n]"
+                                );
+                            }
+                        }
+                        "schema" => {
+                            if args.len() > 1 {
+                                let path = args[1];
+                                self.browser
+                                    .schema(path.to_owned())
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error reading schema of {}: {}", path, e);
+                                    });
+                            } else {
+                                println!("Usage: schema <file>");
+                            }
+                        }
+                        "diff" => {
+                            if args.len() > 3 {
+                                let options = match parse_diff_options(&args[3..]) {
+                                    Ok(options) => options,
+                                    Err(e) => {
+                                        println!("Error: {}", e);
+                                        continue;
+                                    }
+                                };
+                                self.browser
+                                    .diff(args[1], args[2], &options)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!(
+                                            "Error comparing {} and {}: {}",
+                                            args[1], args[2], e
+                                        );
+                                    });
+                            } else {
+                                println!(
+                                    "Usage: diff <file-a> <file-b> --key id[,id2] [--limit N] [--memory MB]"
+                                );
+                            }
+                        }
+                        "agg" => {
+                            if args.len() > 1 {
+                                let options = match parse_agg_options(&args[2..]) {
+                                    Ok(options) => options,
+                                    Err(e) => {
+                                        println!("Error: {}", e);
+                                        continue;
+                                    }
+                                };
+                                self.browser
+                                    .agg(args[1], &options)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error aggregating {}: {}", args[1], e);
+                                    });
+                            } else {
+                                println!(
+                                    "Usage: agg <file-or-dataset> [--by a,b] [--count] [--sum col] [--avg col] [--min col] [--max col] [--where <filter>] [--limit N] [--memory MB]"
+                                );
+                            }
+                        }
+                        "grep" => {
+                            if args.len() > 2 {
+                                let options = match parse_grep_options(args[1], &args[3..]) {
+                                    Ok(options) => options,
+                                    Err(e) => {
+                                        println!("Error: {}", e);
+                                        continue;
+                                    }
+                                };
+                                self.browser
+                                    .grep(args[2], &options)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error searching {}: {}", args[2], e);
+                                    });
+                            } else {
+                                println!(
+                                    "Usage: grep <pattern> <glob> [--columns a,b,...] [--exact] [--limit N]"
+                                );
+                            }
+                        }
+                        "sample" => {
+                            if args.len() > 2 {
+                                let (count, seed) = match parse_sample_args(&args[2..]) {
+                                    Ok(args) => args,
+                                    Err(e) => {
+                                        println!("Error: {}", e);
+                                        continue;
+                                    }
+                                };
+                                let options = pqt::ViewOptions {
+                                    display: Some(self.display),
+                                    terminal_width: self.terminal_width,
+                                    ..Default::default()
+                                };
+                                self.browser
+                                    .sample(args[1], count, seed, &options)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error sampling {}: {}", args[1], e);
+                                    });
+                            } else {
+                                println!("Usage: sample <file-or-dataset> N [--seed S]");
+                            }
+                        }
+                        "schemadiff" => {
+                            if args.len() == 3 {
                                 self.browser
-                                    .view(path.to_owned(), max_rows)
+                                    .schemadiff(args[1], args[2])
                                     .await
                                     .unwrap_or_else(|e| {
-                                        println!("Error viewing file {}: {}", path, e);
+                                        println!("Error comparing schemas: {}", e);
                                     });
                             } else {
-                                println!("Usage: view <file>");
+                                println!("Usage: schemadiff <file-a> <file-b>");
+                            }
+                        }
+                        "schemas" => {
+                            if args.len() == 2 {
+                                self.browser.schemas(args[1]).await.unwrap_or_else(|e| {
+                                    println!("Error reading schemas of {}: {}", args[1], e);
+                                });
+                            } else {
+                                println!("Usage: schemas <glob>");
                             }
                         }
+                        "meta" => {
+                            if args.len() > 1 {
+                                let path = args[1];
+                                let detail = args[2..].contains(&"--detail");
+                                self.browser
+                                    .meta(path.to_owned(), detail)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error reading metadata of {}: {}", path, e);
+                                    });
+                            } else {
+                                println!("Usage: meta <file> [--detail]");
+                            }
+                        }
+                        "stats" => {
+                            if args.len() > 1 {
+                                let path = args[1];
+                                let columns = args[2..].iter().map(|c| c.to_string()).collect();
+                                self.browser
+                                    .stats(path.to_owned(), columns)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error reading statistics of {}: {}", path, e);
+                                    });
+                            } else {
+                                println!("Usage: stats <file> [column...]");
+                            }
+                        }
+                        "\\x" => {
+                            self.display = match args.get(1).copied() {
+                                None if self.display == pqt::DisplayMode::Vertical => {
+                                    pqt::DisplayMode::Table
+                                }
+                                None | Some("on") => pqt::DisplayMode::Vertical,
+                                Some("off") => pqt::DisplayMode::Table,
+                                Some("auto") => pqt::DisplayMode::Auto,
+                                Some(_) => {
+                                    println!("Usage: \\x [on|off|auto]");
+                                    continue;
+                                }
+                            };
+                            println!("Display mode is {:?}", self.display);
+                        }
+                        "profile" => {
+                            if args.len() > 1 {
+                                let path = args[1];
+                                let mut columns = vec![];
+                                let mut sample = None;
+                                let mut i = 2;
+                                while i < args.len() {
+                                    if args[i] == "--sample" {
+                                        match flag_value(&args, &mut i).and_then(|n| {
+                                            n.parse::<usize>()
+                                                .map_err(|_| format!("Invalid sample size: {}", n))
+                                        }) {
+                                            Ok(n) => sample = Some(n),
+                                            Err(e) => {
+                                                println!("Error: {}", e);
+                                                break;
+                                            }
+                                        }
+                                    } else {
+                                        columns.push(args[i].to_string());
+                                    }
+                                    i += 1;
+                                }
+                                if i < args.len() {
+                                    continue;
+                                }
+                                self.browser
+                                    .profile(path.to_owned(), columns, sample)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error profiling {}: {}", path, e);
+                                    });
+                            } else {
+                                println!("Usage: profile <file> [column...] [--sample N]");
+                            }
+                        }
+                        "bloom" => {
+                            if args.len() > 3 {
+                                let path = args[1];
+                                // a string value may contain spaces
+                                let value = args[3..].join(" ");
+                                self.browser
+                                    .bloom(path.to_owned(), args[2], &value)
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error probing bloom filters of {}: {}", path, e);
+                                    });
+                            } else {
+                                println!("Usage: bloom <file> <column> <value>");
+                            }
+                        }
+                        "pages" => {
+                            if args.len() == 3 {
+                                let path = args[1];
+                                self.browser
+                                    .pages(path.to_owned(), args[2])
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        println!("Error reading pages of {}: {}", path, e);
+                                    });
+                            } else {
+                                println!("Usage: pages <file> <column>");
+                            }
+                        }
+                        "verify" => {
+                            if args.len() == 2 {
+                                self.browser.verify(args[1]).await.unwrap_or_else(|e| {
+                                    println!("Error verifying {}: {}", args[1], e);
+                                });
+                            } else {
+                                println!("Usage: verify <glob>");
+                            }
+                        }
+                        "key" => {
+                            if args.len() == 2 || args.len() == 3 {
+                                let name = args[1];
+                                let key = match args.get(2) {
+                                    Some(key) => key.to_string(),
+                                    None => match read_secret(&format!("Key for {}: ", name)) {
+                                        Ok(key) => key.trim().to_string(),
+                                        Err(_) => continue,
+                                    },
+                                };
+                                match self.browser.keys.set(name, &key) {
+                                    Ok(()) if name == encryption::FOOTER => {
+                                        println!("Footer key set")
+                                    }
+                                    Ok(()) => println!("Key of column {} set", name),
+                                    Err(e) => println!("Error: {}", e),
+                                }
+                            } else {
+                                println!("Usage: key <footer|column> [key]");
+                            }
+                        }
+                        "keys" => match args.get(1..) {
+                            Some([]) => {
+                                let keys = &self.browser.keys;
+                                if keys.is_empty() {
+                                    println!("No keys set");
+                                    continue;
+                                }
+                                if keys.has_footer_key() {
+                                    println!("Footer key set");
+                                }
+                                for column in keys.column_names() {
+                                    println!("Key of column {} set", column);
+                                }
+                            }
+                            Some(["load", path]) => match self.browser.load_keys(path) {
+                                Ok(n) => println!("Loaded {} keys from {}", n, path),
+                                Err(e) => println!("Error loading keys from {}: {}", path, e),
+                            },
+                            Some(["clear"]) => {
+                                self.browser.keys = Default::default();
+                                println!("Keys cleared");
+                            }
+                            _ => println!("Usage: keys [load <file>|clear]"),
+                        },
                         "history" => {
                             for (i, cmd) in history.iter().enumerate() {
                                 println!("{}: {}", i + 1, cmd);
@@ -93,7 +509,85 @@ impl Console {
                             println!("  ls [path]     - List files in the directory");
                             println!("  cd <path>     - Change directory");
                             println!("  pwd           - Print current directory");
-                            println!("  view <file>   - View the contents of a file");
+                            println!(
+                                "  view <file> [rows] [--offset N] [--limit M] [--row-group 3|1-3|0,2] [--columns a,b,...] [--nested [col=]flatten|json|compact,...] [-x|--table] [--where <filter>] [--order-by col [desc],...]"
+                            );
+                            println!(
+                                "                - View the contents of a file, columns may be globs"
+                            );
+                            println!(
+                                "                  --order-by shows the top rows by the columns instead of rows in file order"
+                            );
+                            println!("  view --dataset <dir-or-glob> [options]");
+                            println!(
+                                "                - View the files as one table, with year=2024/ style directories as partition columns and _file as a column to select"
+                            );
+                            println!(
+                                "  next / prev   - Show the next or previous rows of the last viewed file"
+                            );
+                            println!(
+                                "  goto <row>    - Show the last viewed file from the given row on"
+                            );
+                            println!(
+                                "  schema <file> - Show the Parquet and Arrow schema of a file"
+                            );
+                            println!(
+                                "  diff <file-a> <file-b> --key id[,id2] [--limit N] [--memory MB]"
+                            );
+                            println!(
+                                "                - Show rows added, removed or changed between two files, which may be paths, URLs or ZIP entries"
+                            );
+                            println!(
+                                "  schemadiff <file-a> <file-b> - Show fields added, removed, reordered or changed in type or nullability"
+                            );
+                            println!(
+                                "  agg <file-or-dataset> [--by a,b] [--count] [--sum col] [--avg col] [--min col] [--max col] [--where <filter>] [--limit N] [--memory MB]"
+                            );
+                            println!(
+                                "                - Aggregate rows by the group columns, the largest groups first; --count when no aggregate is given"
+                            );
+                            println!(
+                                "  grep <pattern> <glob> [--columns a,b,...] [--exact] [--limit N]"
+                            );
+                            println!(
+                                "                - Find rows with values containing the pattern, or equal to it with --exact, in files and ZIP archives"
+                            );
+                            println!(
+                                "  sample <file-or-dataset> N [--seed S] - Show N rows picked at random, with their row numbers"
+                            );
+                            println!(
+                                "  schemas <glob> - Group the matching files by schema and show how the versions differ"
+                            );
+                            println!(
+                                "  count <glob> [--where <filter>] - Count rows of the matching files from their footers"
+                            );
+                            println!(
+                                "  \\x [on|off|auto] - Show rows as vertical records, as tables, or as tables unless too wide"
+                            );
+                            println!(
+                                "  profile <file> [column...] [--sample N] - Profile column values, optionally of the first N rows only"
+                            );
+                            println!(
+                                "  bloom <file> <column> <value> - Show which row groups might contain a value by their bloom filters"
+                            );
+                            println!(
+                                "  pages <file> <column> - List the page headers and dictionary of a column in every row group"
+                            );
+                            println!(
+                                "  verify <glob> - Check the footers, pages, checksums and row counts of the matching files"
+                            );
+                            println!(
+                                "  sql SELECT ... FROM '<glob>' [WHERE ...] [GROUP BY ...] [ORDER BY ...] [LIMIT n]"
+                            );
+                            println!(
+                                "                - Query Parquet files with count, sum, avg, min and max"
+                            );
+                            println!(
+                                "  key <footer|column> [key] - Set the key of encrypted files' footers or of a column, asked for without echo when not given"
+                            );
+                            println!(
+                                "  keys [load <file>|clear] - List the keys set, load them from a file of name: key lines, or clear them"
+                            );
                             println!("  history       - Show command history");
                             println!("  help          - Show this help message");
                             println!("  exit          - Exit the console");
@@ -121,3 +615,326 @@ impl Console {
         }
     }
 }
+
+// Draws every character of the line as a space, so a key typed in isn't shown
+struct Masked;
+
+impl Highlighter for Masked {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(" ".repeat(line.chars().count()))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        kind != CmdKind::MoveCursor
+    }
+}
+
+impl Completer for Masked {
+    type Candidate = String;
+}
+
+impl Hinter for Masked {
+    type Hint = String;
+}
+
+impl Validator for Masked {}
+
+impl Helper for Masked {}
+
+// Read a line without showing it or keeping it in the history
+fn read_secret(prompt: &str) -> rustyline::Result<String> {
+    let mut rl: Editor<Masked, DefaultHistory> = Editor::new()?;
+    rl.set_helper(Some(Masked));
+    rl.set_color_mode(ColorMode::Forced);
+    rl.set_auto_add_history(false);
+    rl.readline(prompt)
+}
+
+// Parse the arguments following `view <file>`
+fn parse_view_options(args: &[&str]) -> Result<pqt::ViewOptions, String> {
+    let mut options = pqt::ViewOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "--columns" => {
+                let value = flag_value(args, &mut i)?;
+                let columns: Vec<String> = value
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+                if columns.is_empty() {
+                    return Err("--columns needs at least one column".to_string());
+                }
+                options.columns = Some(columns);
+            }
+            "--offset" => {
+                let value = flag_value(args, &mut i)?;
+                options.offset = value
+                    .parse()
+                    .map_err(|_| format!("Invalid offset: {}", value))?;
+            }
+            "--limit" => {
+                let value = flag_value(args, &mut i)?;
+                options.max_rows = value
+                    .parse()
+                    .map_err(|_| format!("Invalid limit: {}", value))?;
+            }
+            "--row-group" | "--row-groups" => {
+                let value = flag_value(args, &mut i)?;
+                options.row_groups = Some(parse_row_groups(value)?);
+            }
+            "-x" | "--vertical" => options.display = Some(pqt::DisplayMode::Vertical),
+            "--table" => options.display = Some(pqt::DisplayMode::Table),
+            "--dataset" => options.dataset = true,
+            "--nested" => {
+                let value = flag_value(args, &mut i)?;
+                for part in value.split(',') {
+                    options.nested.push(match part.split_once('=') {
+                        Some((column, mode)) => (Some(column.trim().to_string()), mode.parse()?),
+                        None => (None, part.trim().parse()?),
+                    });
+                }
+            }
+            "--where" => {
+                let value = flag_words(args, &mut i)?;
+                options.filter = Some(Predicate::parse(&value).map_err(|e| e.to_string())?);
+            }
+            "--order-by" => {
+                let value = flag_words(args, &mut i)?;
+                options.order_by = OrderBy::parse_list(&value)?;
+            }
+            arg => {
+                options.max_rows = arg
+                    .parse()
+                    .map_err(|_| format!("Unknown argument: {}", arg))?;
+            }
+        }
+        i += 1;
+    }
+    Ok(options)
+}
+
+// A --memory size in MB, as bytes
+fn parse_memory(value: &str) -> Result<usize, String> {
+    let megabytes: usize = value
+        .parse()
+        .map_err(|_| format!("Invalid memory size in MB: {}", value))?;
+    if megabytes == 0 {
+        return Err("The memory size must be at least 1 MB".to_string());
+    }
+    megabytes
+        .checked_mul(1 << 20)
+        .ok_or_else(|| format!("Memory size too large: {} MB", value))
+}
+
+fn parse_agg_options(args: &[&str]) -> Result<agg::AggOptions, String> {
+    let mut options = agg::AggOptions {
+        by: vec![],
+        aggregates: vec![],
+        filter: None,
+        limit: agg::DEFAULT_AGG_LIMIT,
+        memory: agg::DEFAULT_AGG_MEMORY_MB << 20,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "--by" => {
+                options.by = flag_words(args, &mut i)?
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+            }
+            "--count" => options.aggregates.push(Aggregate {
+                func: AggFunc::Count,
+                column: None,
+            }),
+            flag @ ("--sum" | "--avg" | "--min" | "--max") => {
+                let func = AggFunc::parse(&flag[2..]).expect("matched above");
+                for column in flag_words(args, &mut i)?.split(',') {
+                    options.aggregates.push(Aggregate {
+                        func,
+                        column: Some(column.trim().to_string()),
+                    });
+                }
+            }
+            "--where" => {
+                let value = flag_words(args, &mut i)?;
+                options.filter = Some(Predicate::parse(&value).map_err(|e| e.to_string())?);
+            }
+            "--limit" => {
+                let value = flag_value(args, &mut i)?;
+                options.limit = value
+                    .parse()
+                    .map_err(|_| format!("Invalid limit: {}", value))?;
+            }
+            "--memory" => {
+                let value = flag_value(args, &mut i)?;
+                options.memory = parse_memory(value)?;
+            }
+            arg => return Err(format!("Unknown argument: {}", arg)),
+        }
+        i += 1;
+    }
+    if options.aggregates.is_empty() {
+        options.aggregates.push(Aggregate {
+            func: AggFunc::Count,
+            column: None,
+        });
+    }
+    Ok(options)
+}
+
+fn parse_grep_options(pattern: &str, args: &[&str]) -> Result<grep::GrepOptions, String> {
+    let pattern = pattern
+        .strip_prefix('\'')
+        .and_then(|p| p.strip_suffix('\''))
+        .unwrap_or(pattern);
+    let mut options = grep::GrepOptions {
+        pattern: pattern.to_string(),
+        exact: false,
+        columns: None,
+        limit: grep::DEFAULT_GREP_LIMIT,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "--columns" => {
+                let columns: Vec<String> = flag_value(args, &mut i)?
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+                if columns.is_empty() {
+                    return Err("--columns needs at least one column".to_string());
+                }
+                options.columns = Some(columns);
+            }
+            "--exact" => options.exact = true,
+            "--limit" => {
+                let value = flag_value(args, &mut i)?;
+                options.limit = value
+                    .parse()
+                    .map_err(|_| format!("Invalid limit: {}", value))?;
+            }
+            arg => return Err(format!("Unknown argument: {}", arg)),
+        }
+        i += 1;
+    }
+    Ok(options)
+}
+
+fn parse_diff_options(args: &[&str]) -> Result<diff::DiffOptions, String> {
+    let mut options = diff::DiffOptions {
+        key: vec![],
+        limit: diff::DEFAULT_DIFF_LIMIT,
+        memory: diff::DEFAULT_DIFF_MEMORY_MB << 20,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "--key" => {
+                options.key = flag_value(args, &mut i)?
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+            }
+            "--limit" => {
+                let value = flag_value(args, &mut i)?;
+                options.limit = value
+                    .parse()
+                    .map_err(|_| format!("Invalid limit: {}", value))?;
+            }
+            "--memory" => {
+                let value = flag_value(args, &mut i)?;
+                options.memory = parse_memory(value)?;
+            }
+            arg => return Err(format!("Unknown argument: {}", arg)),
+        }
+        i += 1;
+    }
+    if options.key.is_empty() {
+        return Err("--key needs at least one column".to_string());
+    }
+    Ok(options)
+}
+
+// The row count and seed of `sample`. Without --seed the seed comes from the clock,
+// it's printed with the sample so the same rows can be picked again.
+fn parse_sample_args(args: &[&str]) -> Result<(usize, u64), String> {
+    let count = args[0]
+        .parse()
+        .map_err(|_| format!("Invalid row count: {}", args[0]))?;
+    let mut seed = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i] {
+            "--seed" => {
+                let value = flag_value(args, &mut i)?;
+                seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid seed: {}", value))?,
+                );
+            }
+            arg => return Err(format!("Unknown argument: {}", arg)),
+        }
+        i += 1;
+    }
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    });
+    Ok((count, seed))
+}
+
+// Row group numbers given as a list of numbers and ranges, e.g. 0,3,5-7
+fn parse_row_groups(value: &str) -> Result<Vec<RangeInclusive<usize>>, String> {
+    let invalid = || {
+        format!(
+            "Invalid row groups: {}, expected e.g. 3, 1-3 or 0,2,5-7",
+            value
+        )
+    };
+    let mut row_groups = vec![];
+    for part in value.split(',').map(str::trim) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => (part, part),
+        };
+        let first: usize = first.parse().map_err(|_| invalid())?;
+        let last: usize = last.parse().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        row_groups.push(first..=last);
+    }
+    Ok(row_groups)
+}
+
+// All words following the flag at args[*i] up to the next flag, so that a filter
+// doesn't have to be quoted
+fn flag_words(args: &[&str], i: &mut usize) -> Result<String, String> {
+    let flag = args[*i];
+    let mut words = vec![];
+    while *i + 1 < args.len() && !args[*i + 1].starts_with("--") {
+        *i += 1;
+        words.push(args[*i]);
+    }
+    if words.is_empty() {
+        return Err(format!("{} needs a value", flag));
+    }
+    Ok(words.join(" "))
+}
+
+// Value of the flag at args[*i], advancing past it
+fn flag_value<'a>(args: &[&'a str], i: &mut usize) -> Result<&'a str, String> {
+    let flag = args[*i];
+    *i += 1;
+    args.get(*i)
+        .copied()
+        .ok_or_else(|| format!("{} needs a value", flag))
+}
@@ -0,0 +1,588 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, Int64Array, RecordBatch, RecordBatchOptions, StringArray, UInt32Array,
+    new_null_array,
+};
+use arrow::compute::{can_cast_types, cast, take};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use futures::TryStreamExt;
+use parquet::arrow::arrow_reader::{ArrowReaderMetadata, ArrowReaderOptions, RowSelection};
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::arrow::{
+    ParquetRecordBatchStreamBuilder, ProjectionMask, parquet_to_arrow_schema_by_columns,
+};
+use parquet::schema::types::SchemaDescriptor;
+
+use crate::encryption::{Keys, masked_columns};
+use crate::pqt::{
+    MASK, ViewOptions, ViewPage, print_batches, select_rows, value_to_string, with_row_filter,
+};
+use crate::predicate::{Bound, Predicate};
+use crate::provider::ParquetSource;
+
+// Virtual column with the path of the file each row was read from, shown when asked
+// for by --columns
+pub const FILE_COLUMN: &str = "_file";
+
+// How Hive writes a null partition value
+const HIVE_NULL: &str = "__HIVE_DEFAULT_PARTITION__";
+
+// The files a dataset path stands for: a directory means every Parquet file below it,
+// anything else is taken as a glob
+pub fn dataset_pattern(path: &str) -> String {
+    if path.contains(['*', '?', '[']) {
+        return path.to_string();
+    }
+    match path.trim_end_matches('/') {
+        "" | "." => "**/*.parquet".to_string(),
+        dir => format!("{}/**/*.parquet", dir),
+    }
+}
+
+// Partition values from the `key=value` directories of a path, e.g.
+// `year=2024/month=01/part-0.parquet` has year 2024 and month 01
+pub fn parse_partitions(path: &str) -> Vec<(String, Option<String>)> {
+    let mut dirs: Vec<&str> = path.split('/').collect();
+    dirs.pop();
+    dirs.into_iter()
+        .filter_map(|dir| dir.split_once('='))
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| {
+            let value = (value != HIVE_NULL).then(|| unescape(value));
+            (key.to_string(), value)
+        })
+        .collect()
+}
+
+// Hive escapes characters such as / and = in partition values as %2F and %3D
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = value
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) if bytes[i] == b'%' => {
+                unescaped.push(byte);
+                i += 3;
+            }
+            _ => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).to_string()
+}
+
+// A Parquet file of a dataset and where it lies in the partitioning
+struct DatasetFile {
+    path: String,
+    partitions: Vec<(String, Option<String>)>,
+}
+
+// The partition columns of all files, in the order they first appear. Columns whose
+// values are all integers are Int64, the others Utf8.
+fn partition_fields(files: &[DatasetFile]) -> Vec<Field> {
+    let mut names: Vec<&str> = vec![];
+    for file in files {
+        for (name, _) in file.partitions.iter() {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+    }
+    names
+        .into_iter()
+        .map(|name| {
+            let integers = files
+                .iter()
+                .filter_map(|file| partition_value(file, name))
+                .all(|value| value.parse::<i64>().is_ok());
+            let data_type = if integers {
+                DataType::Int64
+            } else {
+                DataType::Utf8
+            };
+            Field::new(name, data_type, true)
+        })
+        .collect()
+}
+
+fn partition_value<'a>(file: &'a DatasetFile, name: &str) -> Option<&'a str> {
+    file.partitions
+        .iter()
+        .find(|(key, _)| key == name)
+        .and_then(|(_, value)| value.as_deref())
+}
+
+// One row of the values shared by all rows of a file: its partition values and path
+fn file_values(file: &DatasetFile, partitions: &[Field]) -> Result<RecordBatch, Box<dyn Error>> {
+    let mut fields = partitions.to_vec();
+    let mut columns: Vec<ArrayRef> = vec![];
+    for field in partitions {
+        let value = partition_value(file, field.name());
+        columns.push(match field.data_type() {
+            DataType::Int64 => Arc::new(Int64Array::from(vec![value.and_then(|v| v.parse().ok())])),
+            _ => Arc::new(StringArray::from(vec![value])),
+        });
+    }
+    fields.push(Field::new(FILE_COLUMN, DataType::Utf8, false));
+    columns.push(Arc::new(StringArray::from(vec![file.path.as_str()])));
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+// Leaf columns of a file selected by the names or glob patterns, see
+// pqt::projection_mask, and which of the patterns selected any
+pub(crate) fn selected_leaves(
+    schema_descr: &SchemaDescriptor,
+    columns: &[String],
+) -> (Vec<usize>, Vec<bool>) {
+    let patterns: Vec<Option<glob::Pattern>> =
+        columns.iter().map(|c| glob::Pattern::new(c).ok()).collect();
+    let mut matched = vec![false; columns.len()];
+    let mut leaves = vec![];
+    for (idx, descr) in schema_descr.columns().iter().enumerate() {
+        let parts = descr.path().parts();
+        for (i, pattern) in patterns.iter().enumerate() {
+            let Some(pattern) = pattern else { continue };
+            if (1..=parts.len()).any(|n| pattern.matches(&parts[..n].join("."))) {
+                matched[i] = true;
+                if !leaves.contains(&idx) {
+                    leaves.push(idx);
+                }
+            }
+        }
+    }
+    (leaves, matched)
+}
+
+// Opens the files of a dataset by their paths: once to read the footer, and again
+// when the rows are read, so that only one file is open at a time
+#[async_trait::async_trait]
+pub(crate) trait DatasetFiles: Sync {
+    async fn open(&self, path: &str) -> Result<ParquetSource, Box<dyn Error>>;
+}
+
+// The files of a dataset with their footers read, and the columns they make up
+// together
+pub(crate) struct Dataset<'a> {
+    pub(crate) schema: SchemaRef,
+    pub(crate) files: Vec<OpenedFile>,
+    opener: &'a dyn DatasetFiles,
+}
+
+pub(crate) struct OpenedFile {
+    pub(crate) path: String,
+    // the partition values and path shared by all rows of the file
+    pub(crate) known: RecordBatch,
+    // what the filter leaves to check on the rows of the file
+    pub(crate) filter: Option<Predicate>,
+    // top level columns without a key to decrypt them, not read but shown masked
+    pub(crate) masked: Vec<String>,
+    pub(crate) metadata: ArrowReaderMetadata,
+    projection: ProjectionMask,
+    // the row groups, and rows of them, that the statistics and page index leave to read
+    row_groups: Vec<usize>,
+    selection: Option<RowSelection>,
+}
+
+impl Dataset<'_> {
+    // Open a file again to read its rows, with the footer read before, and filter them
+    pub(crate) async fn reader(
+        &self,
+        file: &OpenedFile,
+    ) -> Result<ParquetRecordBatchStreamBuilder<Box<dyn AsyncFileReader>>, Box<dyn Error>> {
+        let source = self.opener.open(&file.path).await?;
+        let mut builder = ParquetRecordBatchStreamBuilder::new_with_metadata(
+            source.reader,
+            file.metadata.clone(),
+        )
+        .with_projection(file.projection.clone())
+        .with_row_groups(file.row_groups.clone());
+        if let Some(selection) = &file.selection {
+            builder = builder.with_row_selection(selection.clone());
+        }
+        if let Some(filter) = &file.filter {
+            builder = with_row_filter(builder, filter)?;
+        }
+        Ok(builder)
+    }
+}
+
+// Read the footers of the files, in path order, leaving out those whose partition
+// values rule out the filter. The footers are all read up front, so the columns stay
+// the same while paging through the dataset; each file is closed again once its footer
+// is read.
+pub(crate) async fn open_dataset<'a>(
+    opener: &'a dyn DatasetFiles,
+    paths: Vec<String>,
+    keys: &Keys,
+    columns: Option<&[String]>,
+    filter: Option<&Predicate>,
+    page_index: bool,
+) -> Result<Dataset<'a>, Box<dyn Error>> {
+    let mut files: Vec<DatasetFile> = paths
+        .into_iter()
+        .map(|path| DatasetFile {
+            partitions: parse_partitions(&path),
+            path,
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let partitions = partition_fields(&files);
+    let names: Vec<&str> = partitions.iter().map(|f| f.name().as_str()).collect();
+    println!(
+        "{} {}{}",
+        files.len(),
+        if files.len() == 1 { "file" } else { "files" },
+        if names.is_empty() {
+            String::new()
+        } else {
+            format!(", partitioned by {}", names.join(", "))
+        }
+    );
+
+    // What the filter leaves to check on the rows of each file
+    let num_files = files.len();
+    let mut read = vec![];
+    for file in files {
+        let known = file_values(&file, &partitions)?;
+        let filter = match filter {
+            Some(filter) => match filter.bind(&known)? {
+                Bound::Never => continue,
+                Bound::Always => None,
+                Bound::Rows(filter) => Some(filter),
+            },
+            None => None,
+        };
+        read.push((file, known, filter));
+    }
+    if filter.is_some() && !partitions.is_empty() {
+        println!(
+            "Skipped {} of {} files using partition values",
+            num_files - read.len(),
+            num_files
+        );
+    }
+
+    let mut matched = vec![false; columns.map_or(0, <[String]>::len)];
+    let mut fields: Vec<Field> = vec![];
+    let mut all_masked: Vec<String> = vec![];
+    // row groups and rows skipped by the statistics, and of how many, over all files
+    let mut pruned = (0, 0);
+    let mut paged: Option<(usize, usize)> = None;
+    let mut opened = vec![];
+    for (file, known, filter) in read {
+        let mut source = opener.open(&file.path).await?;
+        let reader_options = ArrowReaderOptions::new().with_page_index(page_index);
+        let metadata = ArrowReaderMetadata::load_async(&mut source.reader, reader_options)
+            .await
+            .map_err(|e| format!("{}: {}", file.path, e))?;
+        let schema_descr = metadata.metadata().file_metadata().schema_descr_ptr();
+        let mut leaves = match columns {
+            Some(columns) => {
+                let (leaves, matched_here) = selected_leaves(&schema_descr, columns);
+                for (m, here) in matched.iter_mut().zip(matched_here) {
+                    *m |= here;
+                }
+                leaves
+            }
+            None => (0..schema_descr.num_columns()).collect(),
+        };
+        // Partition columns come from the paths, not from the files
+        leaves.retain(|idx| !names.contains(&schema_descr.column(*idx).path().parts()[0].as_str()));
+        let masked: Vec<String> = masked_columns(metadata.metadata(), keys)
+            .into_iter()
+            .filter(|idx| leaves.contains(idx))
+            .map(|idx| schema_descr.column(idx).path().parts()[0].clone())
+            .collect();
+        if let Some(column) = filter
+            .iter()
+            .flat_map(|filter| filter.columns())
+            .find(|column| masked.iter().any(|m| column.split('.').next() == Some(m)))
+        {
+            return Err(format!("{}: no key to decrypt {}", file.path, column).into());
+        }
+        // A column of the filter that the file lacks is null in all its rows
+        let missing: Vec<String> = filter
+            .iter()
+            .flat_map(|filter| filter.columns())
+            .filter(|column| {
+                let name = column.split('.').next().unwrap_or(column);
+                metadata.schema().field_with_name(name).is_err()
+            })
+            .collect();
+        let filter = match filter {
+            Some(filter) if !missing.is_empty() => {
+                let mut known_fields = known.schema().fields().to_vec();
+                let mut values = known.columns().to_vec();
+                for column in missing {
+                    known_fields.push(Arc::new(Field::new(column, DataType::Null, true)));
+                    values.push(new_null_array(&DataType::Null, 1));
+                }
+                let nulls = RecordBatch::try_new(Arc::new(Schema::new(known_fields)), values)?;
+                match filter.bind(&nulls)? {
+                    Bound::Never => continue,
+                    Bound::Always => None,
+                    Bound::Rows(filter) => Some(filter),
+                }
+            }
+            filter => filter,
+        };
+        let mut row_groups: Vec<usize> = (0..metadata.metadata().num_row_groups()).collect();
+        let mut selection = None;
+        if let Some(filter) = &filter {
+            let keep = filter
+                .prune_row_groups(metadata.metadata(), metadata.schema())
+                .map_err(|e| format!("{}: {}", file.path, e))?;
+            let candidates = row_groups.len();
+            row_groups.retain(|idx| keep[*idx]);
+            pruned.0 += candidates - row_groups.len();
+            pruned.1 += candidates;
+            selection = filter
+                .page_selection(metadata.metadata(), metadata.schema(), &row_groups)
+                .map_err(|e| format!("{}: {}", file.path, e))?;
+            if let Some(selection) = &selection {
+                let (skipped, rows) = paged.get_or_insert((0, 0));
+                *skipped += selection.skipped_row_count();
+                *rows += selection.row_count() + selection.skipped_row_count();
+            }
+        }
+        leaves.retain(|idx| !masked.contains(&schema_descr.column(*idx).path().parts()[0]));
+        let mask = ProjectionMask::leaves(&schema_descr, leaves);
+        let schema = parquet_to_arrow_schema_by_columns(
+            &schema_descr,
+            mask.clone(),
+            metadata.metadata().file_metadata().key_value_metadata(),
+        )?;
+        for field in schema.fields() {
+            if !fields.iter().any(|f| f.name() == field.name()) {
+                // a column some files lack is null in their rows
+                fields.push(field.as_ref().clone().with_nullable(true));
+            }
+        }
+        for name in masked.iter() {
+            if !fields.iter().any(|f| f.name() == name) {
+                fields.push(Field::new(name, DataType::Utf8, true));
+            }
+            if !all_masked.contains(name) {
+                all_masked.push(name.clone());
+            }
+        }
+        opened.push(OpenedFile {
+            path: file.path,
+            known,
+            filter,
+            masked,
+            metadata,
+            projection: mask,
+            row_groups,
+            selection,
+        });
+    }
+    // A column masked in any file is shown as text in all of them
+    for field in fields.iter_mut() {
+        if all_masked.contains(field.name()) {
+            *field = Field::new(field.name(), DataType::Utf8, true);
+        }
+    }
+    if !all_masked.is_empty() {
+        println!("Masked, no key to decrypt them: {}", all_masked.join(", "));
+    }
+    if opened.iter().any(|file| file.filter.is_some()) {
+        println!(
+            "Skipped {} of {} row groups using statistics",
+            pruned.0, pruned.1
+        );
+    }
+    if let Some((skipped, rows)) = paged {
+        println!("Skipped {} of {} rows using the page index", skipped, rows);
+    }
+
+    let mut shown_partitions = partitions.clone();
+    let mut show_file = false;
+    if let Some(columns) = columns {
+        let patterns = columns
+            .iter()
+            .map(|c| glob::Pattern::new(c))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, pattern) in patterns.iter().enumerate() {
+            if columns[i] == FILE_COLUMN {
+                show_file = true;
+                matched[i] = true;
+            } else if partitions.iter().any(|f| pattern.matches(f.name())) {
+                matched[i] = true;
+            }
+        }
+        shown_partitions.retain(|f| patterns.iter().any(|p| p.matches(f.name())));
+        if let Some(i) = matched.iter().position(|m| !m) {
+            return Err(format!("No column matches {}", columns[i]).into());
+        }
+    }
+    fields.extend(shown_partitions);
+    if show_file {
+        fields.push(Field::new(FILE_COLUMN, DataType::Utf8, false));
+    }
+    let schema = Arc::new(Schema::new(fields));
+    Ok(Dataset {
+        schema,
+        files: opened,
+        opener,
+    })
+}
+
+// View the files as one table: the columns of all files, then the partition columns
+// parsed from their paths, and `_file` when selected. Files whose partition values
+// rule out the filter aren't read at all.
+pub(crate) async fn dataset_view(
+    opener: &dyn DatasetFiles,
+    paths: Vec<String>,
+    options: &ViewOptions,
+) -> Result<ViewPage, Box<dyn Error>> {
+    if options.row_groups.is_some() {
+        return Err("--row-group can't be combined with --dataset".into());
+    }
+    if !options.order_by.is_empty() {
+        return Err("--order-by can't be combined with --dataset".into());
+    }
+    let page_index = options.filter.is_some() || options.offset > 0;
+    let dataset = open_dataset(
+        opener,
+        paths,
+        &options.keys,
+        options.columns.as_deref(),
+        options.filter.as_ref(),
+        page_index,
+    )
+    .await?;
+
+    let num_rows: usize = dataset
+        .files
+        .iter()
+        .map(|file| file.metadata.metadata().file_metadata().num_rows() as usize)
+        .sum();
+    // Unless the filter is left to check on rows, all rows of the files read match
+    let total = dataset
+        .files
+        .iter()
+        .all(|file| file.filter.is_none())
+        .then_some(num_rows);
+    let mut skip = options.offset;
+    let mut rows = 0;
+    let mut batches = vec![];
+    for file in dataset.files.iter() {
+        if rows >= options.max_rows {
+            break;
+        }
+        let metadata = file.metadata.metadata();
+        let file_rows = metadata.file_metadata().num_rows() as usize;
+        let row_groups: Vec<usize> = (0..metadata.num_row_groups()).collect();
+        // Files in front of the offset are skipped by their row counts, without opening them
+        if file.filter.is_none() && skip >= file_rows {
+            skip -= file_rows;
+            continue;
+        }
+        let mut builder = dataset.reader(file).await?;
+        if file.filter.is_none() {
+            builder = select_rows(builder, &row_groups, skip, options.max_rows - rows);
+            skip = 0;
+        }
+        let mut stream = builder.with_batch_size(8192).build()?;
+        while let Some(batch) = stream.try_next().await? {
+            if skip >= batch.num_rows() {
+                skip -= batch.num_rows();
+                continue;
+            }
+            let wanted = (batch.num_rows() - skip).min(options.max_rows - rows);
+            let batch = batch.slice(skip, wanted);
+            skip = 0;
+            rows += batch.num_rows();
+            batches.push(file.conform(&batch, &dataset.schema)?);
+            if rows >= options.max_rows {
+                break;
+            }
+        }
+    }
+
+    let page = ViewPage {
+        offset: options.offset,
+        rows,
+        total,
+    };
+    if page.rows == 0 {
+        match page.total {
+            Some(total) => println!(
+                "No rows at offset {}, the dataset has {} rows",
+                page.offset, total
+            ),
+            None => println!("No matching rows at offset {}", page.offset),
+        }
+        return Ok(page);
+    }
+    print_batches(&batches, options);
+    let (first, last) = (page.offset + 1, page.offset + page.rows);
+    match page.total {
+        Some(total) => println!("rows {}\u{2013}{} of {}", first, last, total),
+        None => println!(
+            "matching rows {}\u{2013}{} ({} rows in the files read)",
+            first, last, num_rows
+        ),
+    }
+    Ok(page)
+}
+
+impl OpenedFile {
+    // Give a batch read from the file the columns of the dataset: the file's own
+    // columns cast to the dataset's types, nulls for columns the file lacks, asterisks
+    // for the masked ones, and the values the file's rows share repeated
+    pub(crate) fn conform(
+        &self,
+        batch: &RecordBatch,
+        schema: &SchemaRef,
+    ) -> Result<RecordBatch, Box<dyn Error>> {
+        let rows = batch.num_rows();
+        let repeat = UInt32Array::from(vec![0; rows]);
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                if self.masked.contains(field.name()) {
+                    return Ok(Arc::new(StringArray::from(vec![MASK; rows])) as ArrayRef);
+                }
+                Ok(match self.known.column_by_name(field.name()) {
+                    Some(value) => take(value, &repeat, None)?,
+                    None => match batch.column_by_name(field.name()) {
+                        Some(column) if column.data_type() == field.data_type() => column.clone(),
+                        // masked in other files, so shown as text, however nested
+                        Some(column)
+                            if field.data_type() == &DataType::Utf8
+                                && !can_cast_types(column.data_type(), &DataType::Utf8) =>
+                        {
+                            Arc::new(StringArray::from_iter((0..rows).map(|i| {
+                                column
+                                    .is_valid(i)
+                                    .then(|| value_to_string(column.as_ref(), i))
+                            })))
+                        }
+                        Some(column) => cast(column, field.data_type())?,
+                        None => new_null_array(field.data_type(), rows),
+                    },
+                })
+            })
+            .collect::<Result<Vec<ArrayRef>, Box<dyn Error>>>()?;
+        let options = RecordBatchOptions::new().with_row_count(Some(rows));
+        Ok(RecordBatch::try_new_with_options(
+            schema.clone(),
+            columns,
+            &options,
+        )?)
+    }
+}
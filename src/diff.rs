@@ -0,0 +1,692 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+
+use arrow::array::{ArrayRef, RecordBatch};
+use arrow::array::{DynComparator, make_comparator};
+use arrow::compute::{SortOptions, cast};
+use arrow::datatypes::{DataType, Schema};
+use arrow::row::{OwnedRow, RowConverter, Rows, SortField};
+use futures::TryStreamExt;
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+
+use crate::pqt::value_to_string;
+use crate::predicate::column_by_path;
+use crate::provider::ParquetSource;
+use crate::spill::{MAX_PARTITIONS, MAX_SPILL_LEVEL, Partitions};
+use crate::utils::{compute_col_max_len, human_size, print_rows};
+
+// Memory the rows of the first file may take before both files are partitioned to disk
+pub const DEFAULT_DIFF_MEMORY_MB: usize = 256;
+
+// Rows listed of each kind of difference
+pub const DEFAULT_DIFF_LIMIT: usize = 20;
+
+#[derive(Clone, Debug)]
+pub struct DiffOptions {
+    // columns identifying a row in both files
+    pub key: Vec<String>,
+    // added, removed and changed rows listed, those with the lowest keys
+    pub limit: usize,
+    // bytes
+    pub memory: usize,
+}
+
+// How the columns of the two files line up. Values of the second file are cast to
+// the types of the first.
+struct Columns {
+    key: Vec<String>,
+    key_types: Vec<DataType>,
+    key_converter: RowConverter,
+    // columns both files have, other than the key
+    compared: Vec<(String, DataType)>,
+}
+
+impl Columns {
+    fn new(a: &Schema, b: &Schema, key: &[String]) -> Result<Self, Box<dyn Error>> {
+        // An empty batch resolves nested key paths like one with rows
+        let empty_a = RecordBatch::new_empty(a.clone().into());
+        let empty_b = RecordBatch::new_empty(b.clone().into());
+        let mut key_types = vec![];
+        for column in key {
+            let array = column_by_path(&empty_a, column)
+                .map_err(|_| format!("Key column {} isn't in the first file", column))?;
+            column_by_path(&empty_b, column)
+                .map_err(|_| format!("Key column {} isn't in the second file", column))?;
+            key_types.push(array.data_type().clone());
+        }
+        let key_converter = RowConverter::new(
+            key_types
+                .iter()
+                .map(|t| SortField::new(t.clone()))
+                .collect(),
+        )?;
+        let compared = a
+            .fields()
+            .iter()
+            .filter(|f| !key.contains(f.name()) && b.field_with_name(f.name()).is_ok())
+            .map(|f| (f.name().clone(), f.data_type().clone()))
+            .collect();
+        Ok(Self {
+            key: key.to_vec(),
+            key_types,
+            key_converter,
+            compared,
+        })
+    }
+
+    fn keys(&self, batch: &RecordBatch) -> Result<Rows, Box<dyn Error>> {
+        let columns = self
+            .key
+            .iter()
+            .zip(self.key_types.iter())
+            .map(|(column, data_type)| Ok(cast(&column_by_path(batch, column)?, data_type)?))
+            .collect::<Result<Vec<ArrayRef>, Box<dyn Error>>>()?;
+        Ok(self.key_converter.convert_columns(&columns)?)
+    }
+
+    fn compared(&self, batch: &RecordBatch) -> Result<Vec<ArrayRef>, Box<dyn Error>> {
+        self.compared
+            .iter()
+            .map(|(name, data_type)| {
+                let column = batch
+                    .column_by_name(name)
+                    .ok_or_else(|| format!("No such column: {}", name))?;
+                cast(column, data_type)
+                    .map_err(|e| format!("Column {} can't be compared: {}", name, e).into())
+            })
+            .collect()
+    }
+
+    // The values of keys, one string per key column
+    fn key_values<'a>(
+        &self,
+        keys: impl Iterator<Item = &'a OwnedRow>,
+    ) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+        let rows: Vec<_> = keys.map(|k| k.row()).collect();
+        let count = rows.len();
+        let columns = self.key_converter.convert_rows(rows)?;
+        Ok((0..count)
+            .map(|i| columns.iter().map(|c| value_to_string(c, i)).collect())
+            .collect())
+    }
+}
+
+// What the comparison found. Of the differing rows only those with the lowest keys
+// are kept, to be listed.
+struct Report {
+    limit: usize,
+    rows_a: usize,
+    rows_b: usize,
+    added: usize,
+    removed: usize,
+    changed: usize,
+    unchanged: usize,
+    duplicates_a: usize,
+    duplicates_b: usize,
+    // rows changed per compared column
+    changed_columns: Vec<usize>,
+    added_keys: BTreeSet<OwnedRow>,
+    removed_keys: BTreeSet<OwnedRow>,
+    // changed column, value in a and in b
+    changed_rows: BTreeMap<OwnedRow, Vec<(usize, String, String)>>,
+}
+
+impl Report {
+    fn new(columns: &Columns, limit: usize) -> Self {
+        Self {
+            limit,
+            rows_a: 0,
+            rows_b: 0,
+            added: 0,
+            removed: 0,
+            changed: 0,
+            unchanged: 0,
+            duplicates_a: 0,
+            duplicates_b: 0,
+            changed_columns: vec![0; columns.compared.len()],
+            added_keys: BTreeSet::new(),
+            removed_keys: BTreeSet::new(),
+            changed_rows: BTreeMap::new(),
+        }
+    }
+
+    fn keep_key(keys: &mut BTreeSet<OwnedRow>, key: OwnedRow, limit: usize) {
+        keys.insert(key);
+        if keys.len() > limit {
+            keys.pop_last();
+        }
+    }
+
+    // Whether a changed row with this key is among the lowest ones
+    fn lists_changed(&self, key: &OwnedRow) -> bool {
+        self.limit > 0
+            && (self.changed_rows.len() < self.limit
+                || self
+                    .changed_rows
+                    .last_key_value()
+                    .is_some_and(|(last, _)| key < last))
+    }
+
+    fn print(&self, columns: &Columns) -> Result<(), Box<dyn Error>> {
+        let rows = vec![
+            vec![
+                "Rows in a".to_string(),
+                "Rows in b".to_string(),
+                "Added".to_string(),
+                "Removed".to_string(),
+                "Changed".to_string(),
+                "Unchanged".to_string(),
+            ],
+            vec![
+                self.rows_a.to_string(),
+                self.rows_b.to_string(),
+                self.added.to_string(),
+                self.removed.to_string(),
+                self.changed.to_string(),
+                self.unchanged.to_string(),
+            ],
+        ];
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows(&rows, &col_max_len, true);
+        if self.duplicates_a > 0 || self.duplicates_b > 0 {
+            println!(
+                "Duplicate keys: {} rows in a, {} rows in b, only the first row of a key is compared",
+                self.duplicates_a, self.duplicates_b
+            );
+        }
+
+        if self.changed > 0 {
+            println!();
+            let mut rows = vec![vec!["Column".to_string(), "Rows changed".to_string()]];
+            for ((name, _), count) in columns.compared.iter().zip(self.changed_columns.iter()) {
+                if *count > 0 {
+                    rows.push(vec![name.clone(), count.to_string()]);
+                }
+            }
+            let col_max_len = compute_col_max_len(&rows);
+            print_rows(&rows, &col_max_len, true);
+        }
+
+        for (title, keys, count) in [
+            ("Added rows", &self.added_keys, self.added),
+            ("Removed rows", &self.removed_keys, self.removed),
+        ] {
+            if keys.is_empty() {
+                continue;
+            }
+            println!();
+            println!("{} ({} of {}, by lowest key):", title, keys.len(), count);
+            let mut rows = vec![columns.key.clone()];
+            rows.extend(columns.key_values(keys.iter())?);
+            let col_max_len = compute_col_max_len(&rows);
+            print_rows(&rows, &col_max_len, true);
+        }
+
+        if !self.changed_rows.is_empty() {
+            println!();
+            println!(
+                "Changed rows ({} of {}, by lowest key):",
+                self.changed_rows.len(),
+                self.changed
+            );
+            let mut header = columns.key.clone();
+            header.extend(["Column".to_string(), "a".to_string(), "b".to_string()]);
+            let mut rows = vec![header];
+            let keys = columns.key_values(self.changed_rows.keys())?;
+            for (key, differences) in keys.into_iter().zip(self.changed_rows.values()) {
+                for (i, (column, value_a, value_b)) in differences.iter().enumerate() {
+                    // the key is only shown on the first line of a row
+                    let mut row = if i == 0 {
+                        key.clone()
+                    } else {
+                        vec![String::new(); key.len()]
+                    };
+                    row.extend([
+                        columns.compared[*column].0.clone(),
+                        value_a.clone(),
+                        value_b.clone(),
+                    ]);
+                    rows.push(row);
+                }
+            }
+            let col_max_len = compute_col_max_len(&rows);
+            print_rows(&rows, &col_max_len, true);
+        }
+        Ok(())
+    }
+}
+
+// Rows of the first file indexed by key, which rows of the second file are matched
+// against. Rows never matched were removed.
+struct Matcher {
+    batches: Vec<RecordBatch>,
+    // compared columns of each batch, cast
+    compared: Vec<Vec<ArrayRef>>,
+    index: HashMap<OwnedRow, (usize, usize)>,
+    matched: Vec<Vec<bool>>,
+}
+
+impl Matcher {
+    fn new(
+        batches: Vec<RecordBatch>,
+        columns: &Columns,
+        report: &mut Report,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut index = HashMap::new();
+        let mut compared = vec![];
+        let mut matched = vec![];
+        for (idx, batch) in batches.iter().enumerate() {
+            let keys = columns.keys(batch)?;
+            for (i, key) in keys.iter().enumerate() {
+                match index.entry(key.owned()) {
+                    Entry::Occupied(_) => report.duplicates_a += 1,
+                    Entry::Vacant(entry) => {
+                        entry.insert((idx, i));
+                    }
+                }
+            }
+            compared.push(columns.compared(batch)?);
+            matched.push(vec![false; batch.num_rows()]);
+        }
+        Ok(Self {
+            batches,
+            compared,
+            index,
+            matched,
+        })
+    }
+
+    fn compare(
+        &mut self,
+        batch: &RecordBatch,
+        columns: &Columns,
+        report: &mut Report,
+    ) -> Result<(), Box<dyn Error>> {
+        let keys = columns.keys(batch)?;
+        let compared = columns.compared(batch)?;
+        // comparators of this batch's columns with those of each batch of a
+        let mut comparators: HashMap<usize, Vec<DynComparator>> = HashMap::new();
+        for (j, key) in keys.iter().enumerate() {
+            let key = key.owned();
+            let Some(&(idx, i)) = self.index.get(&key) else {
+                report.added += 1;
+                Report::keep_key(&mut report.added_keys, key, report.limit);
+                continue;
+            };
+            if self.matched[idx][i] {
+                report.duplicates_b += 1;
+                continue;
+            }
+            self.matched[idx][i] = true;
+            let batch_comparators = match comparators.entry(idx) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(
+                    self.compared[idx]
+                        .iter()
+                        .zip(compared.iter())
+                        .map(|(a, b)| make_comparator(a, b, SortOptions::default()))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+            };
+            let differences: Vec<usize> = batch_comparators
+                .iter()
+                .enumerate()
+                .filter(|(_, cmp)| cmp(i, j) != Ordering::Equal)
+                .map(|(column, _)| column)
+                .collect();
+            if differences.is_empty() {
+                report.unchanged += 1;
+                continue;
+            }
+            report.changed += 1;
+            for column in differences.iter() {
+                report.changed_columns[*column] += 1;
+            }
+            if report.lists_changed(&key) {
+                let values = differences
+                    .into_iter()
+                    .map(|c| {
+                        (
+                            c,
+                            value_to_string(&self.compared[idx][c], i),
+                            value_to_string(&compared[c], j),
+                        )
+                    })
+                    .collect();
+                report.changed_rows.insert(key, values);
+                if report.changed_rows.len() > report.limit {
+                    report.changed_rows.pop_last();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self, columns: &Columns, report: &mut Report) -> Result<(), Box<dyn Error>> {
+        for (idx, (batch, matched)) in self.batches.iter().zip(self.matched.iter()).enumerate() {
+            if matched.iter().all(|m| *m) {
+                continue;
+            }
+            let keys = columns.keys(batch)?;
+            for (i, key) in keys.iter().enumerate() {
+                // a duplicate key counts once, as the row it was indexed by
+                if !matched[i] && self.index.get(&key.owned()) == Some(&(idx, i)) {
+                    report.removed += 1;
+                    Report::keep_key(&mut report.removed_keys, key.owned(), report.limit);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Compare the rows of two files by key. The first file is held in memory when it
+// fits and the second streamed past it, otherwise both are partitioned by key to
+// disk and compared a partition at a time, see compare_partitions.
+pub async fn parquet_diff(
+    a: ParquetSource,
+    b: ParquetSource,
+    options: &DiffOptions,
+) -> Result<(), Box<dyn Error>> {
+    let (columns, report) = diff(a, b, options).await?;
+    report.print(&columns)
+}
+
+async fn diff(
+    a: ParquetSource,
+    b: ParquetSource,
+    options: &DiffOptions,
+) -> Result<(Columns, Report), Box<dyn Error>> {
+    let builder_a = ParquetRecordBatchStreamBuilder::new(a.reader).await?;
+    let builder_b = ParquetRecordBatchStreamBuilder::new(b.reader).await?;
+    let (schema_a, schema_b) = (builder_a.schema().clone(), builder_b.schema().clone());
+    let columns = Columns::new(&schema_a, &schema_b, &options.key)?;
+
+    let only = |x: &Schema, y: &Schema| -> Vec<String> {
+        x.fields()
+            .iter()
+            .filter(|f| y.field_with_name(f.name()).is_err())
+            .map(|f| f.name().clone())
+            .collect()
+    };
+    for (which, names) in [
+        ("a", only(&schema_a, &schema_b)),
+        ("b", only(&schema_b, &schema_a)),
+    ] {
+        if !names.is_empty() {
+            println!(
+                "Columns only in {}, not compared: {}",
+                which,
+                names.join(", ")
+            );
+        }
+    }
+    for (name, data_type) in columns.compared.iter() {
+        let other = schema_b.field_with_name(name)?.data_type();
+        if other != data_type {
+            println!(
+                "Column {} is {} in a and {} in b, compared as {}",
+                name, data_type, other, data_type
+            );
+        }
+    }
+
+    let estimate: i64 = builder_a
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|rg| rg.total_byte_size())
+        .sum();
+    let mut report = Report::new(&columns, options.limit);
+    let mut stream_a = builder_a.with_batch_size(8192).build()?;
+    let mut held = vec![];
+    let mut held_size = 0;
+    let mut spilled: Option<(Partitions, Partitions)> = None;
+    while let Some(batch) = stream_a.try_next().await? {
+        report.rows_a += batch.num_rows();
+        if let Some((partitions_a, _)) = &mut spilled {
+            partitions_a.write(&batch, &columns.keys(&batch)?)?;
+            continue;
+        }
+        held_size += batch.get_array_memory_size();
+        held.push(batch);
+        if held_size > options.memory {
+            // sized so that a partition of a fits in memory, by an estimate
+            let count = (estimate.max(0) as usize * 2)
+                .div_ceil(options.memory)
+                .clamp(2, MAX_PARTITIONS);
+            println!(
+                "{} doesn't fit in {}, partitioning both files by key into {} spill files",
+                a.filename,
+                human_size(options.memory as i64),
+                count
+            );
+            let mut partitions_a = Partitions::new(count, 0);
+            for batch in held.drain(..) {
+                partitions_a.write(&batch, &columns.keys(&batch)?)?;
+            }
+            spilled = Some((partitions_a, Partitions::new(count, 0)));
+        }
+    }
+
+    let mut stream_b = builder_b.with_batch_size(8192).build()?;
+    match spilled {
+        None => {
+            let mut matcher = Matcher::new(held, &columns, &mut report)?;
+            while let Some(batch) = stream_b.try_next().await? {
+                report.rows_b += batch.num_rows();
+                matcher.compare(&batch, &columns, &mut report)?;
+            }
+            matcher.finish(&columns, &mut report)?;
+        }
+        Some((partitions_a, mut partitions_b)) => {
+            while let Some(batch) = stream_b.try_next().await? {
+                report.rows_b += batch.num_rows();
+                partitions_b.write(&batch, &columns.keys(&batch)?)?;
+            }
+            compare_partitions(
+                partitions_a,
+                partitions_b,
+                &columns,
+                &mut report,
+                options.memory,
+            )?;
+        }
+    }
+    Ok((columns, report))
+}
+
+// Compare the rows of a and b a partition at a time, holding the partition of a in
+// memory. The estimate the partitions were sized by can be off, and keys can be
+// skewed, so a partition of a that doesn't fit is partitioned again, along with
+// the same partition of b, up to MAX_SPILL_LEVEL times.
+fn compare_partitions(
+    mut partitions_a: Partitions,
+    mut partitions_b: Partitions,
+    columns: &Columns,
+    report: &mut Report,
+    memory: usize,
+) -> Result<(), Box<dyn Error>> {
+    for idx in 0..partitions_a.len() {
+        let size = partitions_a.bytes(idx);
+        let level = partitions_a.level() + 1;
+        if size > memory && level <= MAX_SPILL_LEVEL {
+            let count = size.div_ceil(memory).clamp(2, MAX_PARTITIONS);
+            println!(
+                "A partition of {} doesn't fit in {}, partitioning it again into {} spill files",
+                human_size(size as i64),
+                human_size(memory as i64),
+                count
+            );
+            let mut again_a = Partitions::new(count, level);
+            let mut again_b = Partitions::new(count, level);
+            for (from, to) in [
+                (&mut partitions_a, &mut again_a),
+                (&mut partitions_b, &mut again_b),
+            ] {
+                for batch in from.take(idx)?.into_iter().flatten() {
+                    let batch = batch?;
+                    to.write(&batch, &columns.keys(&batch)?)?;
+                }
+            }
+            compare_partitions(again_a, again_b, columns, report, memory)?;
+            continue;
+        }
+        let batches = match partitions_a.take(idx)? {
+            Some(reader) => reader.collect::<Result<Vec<_>, _>>()?,
+            None => vec![],
+        };
+        let mut matcher = Matcher::new(batches, columns, report)?;
+        for batch in partitions_b.take(idx)?.into_iter().flatten() {
+            matcher.compare(&batch?, columns, report)?;
+        }
+        matcher.finish(columns, report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::Field;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+
+    use super::*;
+    use crate::pqt::reader_from_bytes;
+
+    // An in-memory file of rows by id and name, in row groups of 50 rows
+    fn file(ids: &[i64], name: impl Fn(i64) -> String) -> ParquetSource {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let names: Vec<String> = ids.iter().map(|id| name(*id)).collect();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(ids.to_vec())),
+                Arc::new(StringArray::from(names)),
+            ],
+        )
+        .unwrap();
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(50)
+            .build();
+        let mut content = vec![];
+        let mut writer = ArrowWriter::try_new(&mut content, schema, Some(properties)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        ParquetSource {
+            filename: "test.parquet".to_string(),
+            reader: reader_from_bytes(content),
+            size: None,
+        }
+    }
+
+    // What the report lists, comparable between runs
+    fn summary(columns: &Columns, report: &Report) -> Vec<String> {
+        vec![
+            format!(
+                "rows {} {}, added {}, removed {}, changed {}, unchanged {}",
+                report.rows_a,
+                report.rows_b,
+                report.added,
+                report.removed,
+                report.changed,
+                report.unchanged
+            ),
+            format!(
+                "duplicates {} {}, columns {:?}",
+                report.duplicates_a, report.duplicates_b, report.changed_columns
+            ),
+            format!(
+                "added {:?}",
+                columns.key_values(report.added_keys.iter()).unwrap()
+            ),
+            format!(
+                "removed {:?}",
+                columns.key_values(report.removed_keys.iter()).unwrap()
+            ),
+            format!(
+                "changed {:?} {:?}",
+                columns.key_values(report.changed_rows.keys()).unwrap(),
+                report.changed_rows.values().collect::<Vec<_>>()
+            ),
+        ]
+    }
+
+    // Diff the files with rows held in memory, and again with a memory cap of a byte,
+    // which partitions both files and every partition again up to MAX_SPILL_LEVEL
+    async fn diff_both_ways(
+        a: impl Fn() -> ParquetSource,
+        b: impl Fn() -> ParquetSource,
+    ) -> Vec<String> {
+        let mut options = DiffOptions {
+            key: vec!["id".to_string()],
+            limit: 5,
+            memory: DEFAULT_DIFF_MEMORY_MB << 20,
+        };
+        let (columns, report) = diff(a(), b(), &options).await.unwrap();
+        let in_memory = summary(&columns, &report);
+        options.memory = 1;
+        let (columns, report) = diff(a(), b(), &options).await.unwrap();
+        assert_eq!(summary(&columns, &report), in_memory);
+        in_memory
+    }
+
+    #[tokio::test]
+    async fn spilled_diff_matches_in_memory() {
+        let a = || file(&(0..300).collect::<Vec<_>>(), |id| format!("name-{}", id));
+        let b = || {
+            file(&(50..350).rev().collect::<Vec<_>>(), |id| {
+                if id % 7 == 0 {
+                    format!("changed-{}", id)
+                } else {
+                    format!("name-{}", id)
+                }
+            })
+        };
+        let summary = diff_both_ways(a, b).await;
+        assert_eq!(
+            summary[0],
+            "rows 300 300, added 50, removed 50, changed 35, unchanged 215"
+        );
+        assert_eq!(
+            summary[2],
+            r#"added [["300"], ["301"], ["302"], ["303"], ["304"]]"#
+        );
+        assert_eq!(summary[3], r#"removed [["0"], ["1"], ["2"], ["3"], ["4"]]"#);
+        assert!(summary[4].starts_with(r#"changed [["56"], ["63"], ["70"], ["77"], ["84"]]"#));
+    }
+
+    #[tokio::test]
+    async fn duplicate_keys_count_once() {
+        let a = || file(&[1, 1, 2, 3, 3, 3], |id| format!("name-{}", id));
+        let b = || file(&[2, 2, 3, 4], |id| format!("name-{}", id));
+        let summary = diff_both_ways(a, b).await;
+        assert_eq!(
+            summary[0],
+            "rows 6 4, added 1, removed 1, changed 0, unchanged 2"
+        );
+        assert_eq!(summary[1], "duplicates 3 1, columns [0]");
+    }
+
+    // Rows of one key can't be split by partitioning, so past MAX_SPILL_LEVEL
+    // their partition is compared whole
+    #[tokio::test]
+    async fn one_key_stops_at_max_spill_level() {
+        let a = || file(&[7; 200], |_| "a".to_string());
+        let b = || file(&[7; 100], |_| "b".to_string());
+        let summary = diff_both_ways(a, b).await;
+        assert_eq!(
+            summary[0],
+            "rows 200 100, added 0, removed 0, changed 1, unchanged 0"
+        );
+        assert_eq!(summary[1], "duplicates 199 99, columns [1]");
+    }
+}
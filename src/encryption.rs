@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ops::Range;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use parquet::arrow::arrow_reader::ArrowReaderOptions;
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::encryption::decrypt::FileDecryptionProperties;
+use parquet::errors::ParquetError;
+use parquet::file::FOOTER_SIZE;
+use parquet::file::column_crypto_metadata::ColumnCryptoMetaData;
+use parquet::file::metadata::{FooterTail, ParquetMetaData};
+
+// Name a key file and the `key` command use for the footer key
+pub const FOOTER: &str = "footer";
+
+// Keys of Parquet modular encryption: the footer key, which also decrypts columns
+// encrypted with it, and keys of columns encrypted with their own
+#[derive(Clone, Default)]
+pub struct Keys {
+    footer: Option<Vec<u8>>,
+    columns: BTreeMap<String, Vec<u8>>,
+}
+
+// Never print the keys themselves
+impl std::fmt::Debug for Keys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keys")
+            .field("footer", &self.footer.is_some())
+            .field("columns", &self.columns.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Keys {
+    pub fn is_empty(&self) -> bool {
+        self.footer.is_none() && self.columns.is_empty()
+    }
+
+    pub fn has_footer_key(&self) -> bool {
+        self.footer.is_some()
+    }
+
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.keys().map(String::as_str).collect()
+    }
+
+    // Set the footer key, or the key of a column by its dotted path
+    pub fn set(&mut self, name: &str, key: &str) -> Result<(), Box<dyn Error>> {
+        let key = parse_key(key)?;
+        if name == FOOTER {
+            self.footer = Some(key);
+        } else {
+            self.columns.insert(name.to_string(), key);
+        }
+        Ok(())
+    }
+
+    // Read `name: key` lines, where the name is `footer` or a column path. Blank lines
+    // and lines starting with # are skipped.
+    pub fn load(&mut self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let mut loaded = 0;
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, key) = line
+                .split_once(':')
+                .ok_or_else(|| format!("Line {}: expected <footer|column>: <key>", i + 1))?;
+            self.set(name.trim(), key.trim())
+                .map_err(|e| format!("Line {}: {}", i + 1, e))?;
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    // Whether a column chunk encrypted this way can be decrypted with these keys
+    pub fn can_decrypt(&self, crypto: &ColumnCryptoMetaData) -> bool {
+        match crypto {
+            ColumnCryptoMetaData::ENCRYPTION_WITH_FOOTER_KEY => self.footer.is_some(),
+            ColumnCryptoMetaData::ENCRYPTION_WITH_COLUMN_KEY(column) => {
+                self.columns.contains_key(&column.path_in_schema.join("."))
+            }
+        }
+    }
+
+    pub fn decryption_properties(
+        &self,
+    ) -> Result<Option<Arc<FileDecryptionProperties>>, Box<dyn Error>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        // A plaintext footer can be read with column keys alone, only its signature
+        // can't be checked then
+        let mut builder = match &self.footer {
+            Some(key) => FileDecryptionProperties::builder(key.clone()),
+            None => FileDecryptionProperties::builder(vec![0; 16])
+                .disable_footer_signature_verification(),
+        };
+        for (column, key) in self.columns.iter() {
+            builder = builder.with_column_key(column, key.clone());
+        }
+        Ok(Some(builder.build()?))
+    }
+}
+
+// AES keys are 16, 24 or 32 bytes, given either as hex or as the text itself
+fn parse_key(key: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let valid = |len: usize| matches!(len, 16 | 24 | 32);
+    if key.len().is_multiple_of(2)
+        && valid(key.len() / 2)
+        && key.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Ok((0..key.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&key[i..i + 2], 16).expect("checked to be hex"))
+            .collect());
+    }
+    if valid(key.len()) {
+        return Ok(key.as_bytes().to_vec());
+    }
+    Err("A key must be 16, 24 or 32 bytes, as text or as hex".into())
+}
+
+// Leaf columns, by index, which are encrypted and can't be decrypted with the keys.
+// Columns are encrypted the same way in every row group.
+pub(crate) fn masked_columns(metadata: &ParquetMetaData, keys: &Keys) -> Vec<usize> {
+    let Some(rg) = metadata.row_groups().first() else {
+        return vec![];
+    };
+    rg.columns()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| {
+            c.crypto_metadata()
+                .is_some_and(|crypto| !keys.can_decrypt(crypto))
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+// Which key each encrypted column needs, e.g. `ssn (column key)`
+pub(crate) fn describe_columns(metadata: &ParquetMetaData) -> Vec<String> {
+    let Some(rg) = metadata.row_groups().first() else {
+        return vec![];
+    };
+    rg.columns()
+        .iter()
+        .filter_map(|c| {
+            let key = match c.crypto_metadata()? {
+                ColumnCryptoMetaData::ENCRYPTION_WITH_FOOTER_KEY => "footer key",
+                ColumnCryptoMetaData::ENCRYPTION_WITH_COLUMN_KEY(_) => "column key",
+            };
+            Some(format!("{} ({})", c.column_path().string(), key))
+        })
+        .collect()
+}
+
+// Reads a Parquet file with the session's keys. When the footer can't be read, the
+// magic at the end of the file and the keys at hand tell what to do about it.
+pub struct KeyedReader {
+    inner: Box<dyn AsyncFileReader>,
+    size: Option<u64>,
+    keys: Keys,
+    decryption: Option<Arc<FileDecryptionProperties>>,
+}
+
+impl KeyedReader {
+    pub fn new(
+        inner: Box<dyn AsyncFileReader>,
+        size: Option<u64>,
+        keys: &Keys,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            inner,
+            size,
+            keys: keys.clone(),
+            decryption: keys.decryption_properties()?,
+        })
+    }
+
+    // The last 8 bytes of the file, which say whether the footer is encrypted
+    async fn footer_tail(&mut self) -> Option<FooterTail> {
+        let size = self.size.filter(|size| *size >= FOOTER_SIZE as u64)?;
+        let bytes = self
+            .inner
+            .get_bytes(size - FOOTER_SIZE as u64..size)
+            .await
+            .ok()?;
+        let tail: [u8; FOOTER_SIZE] = bytes.as_ref().try_into().ok()?;
+        FooterTail::try_from(tail).ok()
+    }
+
+    async fn explain(&mut self, e: ParquetError) -> ParquetError {
+        let Some(tail) = self.footer_tail().await else {
+            if self.keys.is_empty() {
+                return e;
+            }
+            return ParquetError::General(format!(
+                "The file can't be read with the loaded keys: {}",
+                e
+            ));
+        };
+        let explanation = if tail.is_encrypted_footer() {
+            if self.keys.has_footer_key() {
+                "The footer key doesn't decrypt the footer, it's the wrong key"
+            } else {
+                "The file has an encrypted footer, set the footer key with `key footer` or load a key file with `keys load <file>`"
+            }
+        } else {
+            // A plaintext footer is only signed in files with encrypted columns, and only
+            // checked with the footer key
+            let plaintext = self
+                .inner
+                .get_metadata(Some(&ArrowReaderOptions::new()))
+                .await;
+            let encrypted = plaintext.is_ok_and(|metadata| {
+                metadata
+                    .row_groups()
+                    .iter()
+                    .flat_map(|rg| rg.columns())
+                    .any(|c| c.crypto_metadata().is_some())
+            });
+            if !encrypted || !self.keys.has_footer_key() {
+                return e;
+            }
+            "The footer key doesn't match the signature of the plaintext footer, it's the wrong key"
+        };
+        ParquetError::General(explanation.to_string())
+    }
+}
+
+impl AsyncFileReader for KeyedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, parquet::errors::Result<Bytes>> {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, parquet::errors::Result<Vec<Bytes>>> {
+        self.inner.get_byte_ranges(ranges)
+    }
+
+    // The decryptor built from the keys ends up in the metadata, which is where the
+    // decoder takes it from
+    fn get_metadata<'a>(
+        &'a mut self,
+        options: Option<&'a ArrowReaderOptions>,
+    ) -> BoxFuture<'a, parquet::errors::Result<Arc<ParquetMetaData>>> {
+        async move {
+            let mut options = options.cloned().unwrap_or_default();
+            if let Some(decryption) = &self.decryption {
+                options = options.with_file_decryption_properties(decryption.clone());
+            }
+            let e = match self.inner.get_metadata(Some(&options)).await {
+                Ok(metadata) => return Ok(metadata),
+                Err(e) => e,
+            };
+            // The page index of a column without its key can't be decrypted either,
+            // do without the page index then
+            if options.page_index() {
+                let options = options.with_page_index(false);
+                if let Ok(metadata) = self.inner.get_metadata(Some(&options)).await
+                    && !masked_columns(&metadata, &self.keys).is_empty()
+                {
+                    return Ok(metadata);
+                }
+            }
+            Err(self.explain(e).await)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,342 @@
+use std::error::Error;
+
+use arrow::array::{Array, AsArray, BooleanArray, Scalar, StringArray};
+use arrow::compute::kernels::cmp::eq;
+use arrow::compute::kernels::comparison::contains;
+use arrow::compute::{CastOptions, can_cast_types, cast, cast_with_options};
+use arrow::datatypes::{DataType, Field};
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::arrow::{
+    ParquetRecordBatchStreamBuilder, ProjectionMask, parquet_to_arrow_schema_by_columns,
+};
+
+use crate::dataset::selected_leaves;
+use crate::pqt::bloom_check;
+use crate::predicate::{CompareOp, Literal, Predicate, column_by_path, statistics_converter};
+use crate::utils::{compute_col_max_len, print_rows_left};
+
+pub const DEFAULT_GREP_LIMIT: usize = 20;
+
+#[derive(Clone, Debug)]
+pub struct GrepOptions {
+    pub pattern: String,
+    // the whole value has to equal the pattern, which lets row groups be skipped
+    pub exact: bool,
+    // column names or glob patterns, all columns when not set
+    pub columns: Option<Vec<String>>,
+    // matching rows shown, the search stops after as many
+    pub limit: usize,
+}
+
+// A search for values across files, one file at a time, collecting matching rows
+pub struct Grep {
+    options: GrepOptions,
+    rows: Vec<Vec<String>>,
+    files: usize,
+    files_matched: usize,
+    row_groups: usize,
+    row_groups_skipped: usize,
+}
+
+// A column the search looks at, by its dotted path
+struct SearchColumn {
+    path: String,
+    data_type: DataType,
+    leaf: usize,
+}
+
+impl Grep {
+    pub fn new(options: GrepOptions) -> Self {
+        Self {
+            options,
+            rows: vec![],
+            files: 0,
+            files_matched: 0,
+            row_groups: 0,
+            row_groups_skipped: 0,
+        }
+    }
+
+    // Enough rows matched to stop searching
+    pub fn is_done(&self) -> bool {
+        self.rows.len() >= self.options.limit
+    }
+
+    // Search the columns of a file for the pattern. With an exact pattern row groups
+    // whose statistics or bloom filters rule the value out in every column are
+    // skipped before decoding anything.
+    pub async fn search<T: AsyncFileReader + Unpin + Send + 'static>(
+        &mut self,
+        filename: &str,
+        reader: T,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
+        let metadata = builder.metadata().clone();
+        let schema_descr = builder.parquet_schema().clone();
+        let arrow_schema = builder.schema().clone();
+        self.files += 1;
+        self.row_groups += metadata.num_row_groups();
+
+        let leaves = match &self.options.columns {
+            Some(columns) => selected_leaves(&schema_descr, columns).0,
+            None => (0..schema_descr.num_columns()).collect(),
+        };
+        let schema = parquet_to_arrow_schema_by_columns(
+            &schema_descr,
+            ProjectionMask::leaves(&schema_descr, leaves.clone()),
+            metadata.file_metadata().key_value_metadata(),
+        )?;
+        let mut columns = vec![];
+        for field in schema.fields() {
+            search_columns(field, "", &mut columns);
+        }
+        for column in columns.iter_mut() {
+            column.leaf = leaves
+                .iter()
+                .copied()
+                .find(|idx| schema_descr.column(*idx).path().string() == column.path)
+                .unwrap_or(usize::MAX);
+        }
+        columns.retain(|c| c.leaf != usize::MAX);
+        if columns.is_empty() {
+            self.row_groups_skipped += metadata.num_row_groups();
+            return Ok(());
+        }
+
+        let mut row_groups: Vec<usize> = (0..metadata.num_row_groups()).collect();
+        if self.options.exact {
+            let mut may_match = vec![false; metadata.num_row_groups()];
+            for column in columns.iter() {
+                let pattern = &self.options.pattern;
+                let parses = parses_as(&column.data_type, pattern);
+                if !parses && !shown_as_text(&column.data_type) {
+                    continue;
+                }
+                let has_statistics =
+                    statistics_converter(&column.path, &arrow_schema, &schema_descr)?.is_some();
+                // Statistics leave NaN out, so they can't rule out floating point values,
+                // and a NaN may be stored with other bits than the one parsed
+                let floating = value_type(&column.data_type).is_floating();
+                let nan = floating && pattern.parse::<f64>().is_ok_and(f64::is_nan);
+                let mut in_column = if parses && has_statistics && !floating {
+                    Predicate::Compare {
+                        column: column.path.clone(),
+                        op: CompareOp::Eq,
+                        value: Literal::Str(pattern.clone()),
+                    }
+                    .prune_row_groups(&metadata, &arrow_schema)?
+                } else {
+                    vec![true; metadata.num_row_groups()]
+                };
+                if bloom_applies(&column.data_type) && !nan {
+                    let descr = schema_descr.column(column.leaf);
+                    for (rg, may) in in_column.iter_mut().enumerate() {
+                        if *may
+                            && let Some(filter) = builder
+                                .get_row_group_column_bloom_filter(rg, column.leaf)
+                                .await?
+                        {
+                            *may = bloom_check(
+                                &filter,
+                                &descr,
+                                value_type(&column.data_type),
+                                pattern,
+                            )
+                            .unwrap_or(true);
+                        }
+                    }
+                }
+                for (may, here) in may_match.iter_mut().zip(in_column) {
+                    *may |= here;
+                }
+            }
+            row_groups.retain(|rg| may_match[*rg]);
+            self.row_groups_skipped += metadata.num_row_groups() - row_groups.len();
+        }
+
+        let first_rows: Vec<usize> = metadata
+            .row_groups()
+            .iter()
+            .scan(0, |first, rg| {
+                let row = *first;
+                *first += rg.num_rows() as usize;
+                Some(row)
+            })
+            .collect();
+        let mask = ProjectionMask::leaves(&schema_descr, columns.iter().map(|c| c.leaf));
+        let mut stream = builder
+            .with_projection(mask)
+            .with_row_groups(row_groups.clone())
+            .with_batch_size(8192)
+            .build()?;
+        let pattern = Scalar::new(StringArray::from(vec![self.options.pattern.as_str()]));
+        let matched_before = self.rows.len();
+        for rg in row_groups {
+            let Some(reader) = stream.next_row_group().await? else {
+                break;
+            };
+            let mut row = first_rows[rg];
+            for batch in reader {
+                let batch = batch?;
+                let mut values = vec![];
+                for column in columns.iter() {
+                    let array = cast(&column_by_path(&batch, &column.path)?, &DataType::Utf8)?;
+                    let matches = if self.options.exact {
+                        eq(&array, &pattern)?
+                    } else {
+                        contains(&array, &pattern)?
+                    };
+                    values.push((array, matches));
+                }
+                for i in 0..batch.num_rows() {
+                    let found: Vec<String> = columns
+                        .iter()
+                        .zip(values.iter())
+                        .filter(|(_, (_, matches))| is_match(matches, i))
+                        .map(|(column, (array, _))| {
+                            format!("{}={}", column.path, array.as_string::<i32>().value(i))
+                        })
+                        .collect();
+                    if found.is_empty() {
+                        continue;
+                    }
+                    self.rows.push(vec![
+                        filename.to_string(),
+                        rg.to_string(),
+                        (row + i + 1).to_string(),
+                        found.join(", "),
+                    ]);
+                    if self.is_done() {
+                        break;
+                    }
+                }
+                row += batch.num_rows();
+                if self.is_done() {
+                    break;
+                }
+            }
+            if self.is_done() {
+                break;
+            }
+        }
+        if self.rows.len() > matched_before {
+            self.files_matched += 1;
+        }
+        Ok(())
+    }
+
+    pub fn print(&self) {
+        if !self.rows.is_empty() {
+            let mut rows = vec![vec![
+                "File".to_string(),
+                "Row group".to_string(),
+                "Row".to_string(),
+                "Matches".to_string(),
+            ]];
+            rows.extend(self.rows.iter().cloned());
+            let col_max_len = compute_col_max_len(&rows);
+            print_rows_left(&rows, &col_max_len, true);
+        }
+        println!(
+            "{} matching {} in {} of {} files",
+            self.rows.len(),
+            if self.rows.len() == 1 { "row" } else { "rows" },
+            self.files_matched,
+            self.files
+        );
+        if self.options.exact {
+            println!(
+                "Skipped {} of {} row groups using statistics and bloom filters",
+                self.row_groups_skipped, self.row_groups
+            );
+        }
+        if self.is_done() {
+            println!(
+                "Stopped after {} matching rows, use --limit to find more",
+                self.options.limit
+            );
+        }
+    }
+}
+
+// The columns below a field whose values can be searched as strings; lists, maps and
+// binary values are left out
+fn search_columns(field: &Field, parent: &str, columns: &mut Vec<SearchColumn>) {
+    let path = if parent.is_empty() {
+        field.name().clone()
+    } else {
+        format!("{}.{}", parent, field.name())
+    };
+    match field.data_type() {
+        DataType::Struct(fields) => {
+            for child in fields.iter() {
+                search_columns(child, &path, columns);
+            }
+        }
+        DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => {}
+        data_type if can_cast_types(data_type, &DataType::Utf8) && !data_type.is_nested() => {
+            columns.push(SearchColumn {
+                path,
+                data_type: data_type.clone(),
+                leaf: usize::MAX,
+            });
+        }
+        _ => {}
+    }
+}
+
+fn value_type(data_type: &DataType) -> &DataType {
+    match data_type {
+        DataType::Dictionary(_, value_type) => value_type.as_ref(),
+        data_type => data_type,
+    }
+}
+
+// Numbers and booleans are shown in a form that parses back, so a pattern that
+// doesn't parse as one can't equal any of their values. Other values, e.g. dates,
+// may be shown differently from what parses.
+fn shown_as_text(data_type: &DataType) -> bool {
+    let value_type = value_type(data_type);
+    !value_type.is_numeric() && *value_type != DataType::Boolean
+}
+
+fn parses_as(data_type: &DataType, pattern: &str) -> bool {
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    cast_with_options(
+        &StringArray::from(vec![pattern]),
+        value_type(data_type),
+        &options,
+    )
+    .is_ok()
+}
+
+// Bloom filters hash the stored value, which for these types is the value as shown
+fn bloom_applies(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Utf8View
+            | DataType::Boolean
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+    )
+}
+
+fn is_match(matches: &BooleanArray, i: usize) -> bool {
+    matches.is_valid(i) && matches.value(i)
+}
@@ -1,6 +1,22 @@
+pub mod agg;
+pub mod aggregate;
 pub mod browser;
 pub mod console;
+pub mod dataset;
+pub mod diff;
+pub mod encryption;
+pub mod grep;
+pub mod pages;
 pub mod pqt;
+pub mod predicate;
+pub mod profile;
 pub mod provider;
+pub mod render;
+pub mod sample;
+pub mod schemas;
+pub mod spill;
+pub mod sql;
+pub mod topn;
 pub mod utils;
+pub mod verify;
 pub mod zip;
@@ -0,0 +1,458 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes};
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::basic::Type as PhysicalType;
+use parquet::column::page::{Page, PageReader};
+use parquet::errors::ParquetError;
+use parquet::file::metadata::ColumnChunkMetaData;
+use parquet::file::reader::{ChunkReader, Length};
+use parquet::file::serialized_reader::SerializedPageReader;
+
+use crate::pqt::{leaf_column, read_metadata};
+use crate::utils::{compute_col_max_len, human_size, print_rows};
+
+// Dictionary entries shown after the page list
+const DICTIONARY_PREVIEW: usize = 10;
+
+// Print the header of every page of a column chunk in each row group, followed by the
+// size and first entries of the dictionary. The headers are decoded straight from the
+// chunk bytes, the arrow reader never exposes them.
+pub async fn parquet_pages<T: AsyncFileReader>(
+    mut reader: T,
+    column: &str,
+) -> Result<(), Box<dyn Error>> {
+    let metadata = read_metadata(&mut reader, false).await?;
+    let schema_descr = metadata.file_metadata().schema_descr();
+    let column_idx = leaf_column(schema_descr, column)?;
+    let descr = schema_descr.column(column_idx);
+
+    for (idx, rg) in metadata.row_groups().iter().enumerate() {
+        let chunk = rg.column(column_idx);
+        let (start, length) = chunk.byte_range();
+        let data = reader.get_bytes(start..start + length).await?;
+        println!(
+            "Row group {}: column {} ({}, {}), {} compressed, {} uncompressed",
+            idx,
+            column,
+            descr.physical_type(),
+            chunk.compression(),
+            human_size(chunk.compressed_size()),
+            human_size(chunk.uncompressed_size())
+        );
+
+        let mut rows = vec![
+            [
+                "Page",
+                "Type",
+                "Offset",
+                "Header",
+                "Compressed",
+                "Uncompressed",
+                "Values",
+                "Nulls",
+                "Rows",
+                "Encoding",
+                "CRC",
+            ]
+            .map(String::from)
+            .to_vec(),
+        ];
+        for page in page_headers(start, &data)? {
+            let header = page.header;
+            rows.push(vec![
+                (rows.len() - 1).to_string(),
+                header.page_type.to_string(),
+                page.offset.to_string(),
+                human_size(page.header_size as i64),
+                human_size(header.compressed_size),
+                human_size(header.uncompressed_size),
+                header.values.map(|n| n.to_string()).unwrap_or_default(),
+                header.nulls.map(|n| n.to_string()).unwrap_or_default(),
+                header.rows.map(|n| n.to_string()).unwrap_or_default(),
+                header.encoding,
+                header
+                    .crc
+                    .map(|crc| format!("{:08x}", crc as u32))
+                    .unwrap_or_default(),
+            ]);
+        }
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows(&rows, &col_max_len, true);
+
+        if chunk.dictionary_page_offset().is_some() {
+            print_dictionary(ChunkBytes { start, data }, chunk, rg.num_rows() as usize)?;
+        }
+        println!();
+    }
+    Ok(())
+}
+
+// A page of a column chunk: where it starts, the size of its header and the header
+pub(crate) struct ChunkPage {
+    pub(crate) offset: u64,
+    pub(crate) header_size: usize,
+    pub(crate) header: PageHeader,
+}
+
+// Walk the page headers of a column chunk read from file offset `start`
+pub(crate) fn page_headers(start: u64, data: &[u8]) -> Result<Vec<ChunkPage>, String> {
+    let mut pages = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut reader = CompactReader::new(&data[pos..]);
+        let fields = reader.read_struct().map_err(|e| {
+            format!(
+                "Invalid page header at offset {}: {}",
+                start + pos as u64,
+                e
+            )
+        })?;
+        let header = PageHeader::new(&fields);
+        let page_size = reader.pos + header.compressed_size.max(0) as usize;
+        pages.push(ChunkPage {
+            offset: start + pos as u64,
+            header_size: reader.pos,
+            header,
+        });
+        pos += page_size;
+    }
+    Ok(pages)
+}
+
+fn print_dictionary(
+    chunk_bytes: ChunkBytes,
+    chunk: &ColumnChunkMetaData,
+    num_rows: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut pages = SerializedPageReader::new(Arc::new(chunk_bytes), chunk, num_rows, None)?;
+    let Some(Page::DictionaryPage {
+        buf,
+        num_values,
+        encoding,
+        ..
+    }) = pages.get_next_page()?
+    else {
+        return Ok(());
+    };
+    let descr = chunk.column_descr();
+    let entries = plain_values(
+        &buf,
+        descr.physical_type(),
+        descr.type_length(),
+        DICTIONARY_PREVIEW.min(num_values as usize),
+    );
+    println!(
+        "Dictionary: {} entries, {} uncompressed, {}",
+        num_values,
+        human_size(buf.len() as i64),
+        encoding
+    );
+    println!(
+        "  {}{}",
+        entries.join(", "),
+        if num_values as usize > entries.len() {
+            ", ..."
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
+
+// The first n values of a plain encoded page, fewer when the buffer ends early
+fn plain_values(
+    buf: &[u8],
+    physical_type: PhysicalType,
+    type_length: i32,
+    n: usize,
+) -> Vec<String> {
+    let mut values = vec![];
+    let mut buf = buf;
+    while values.len() < n {
+        let width = match physical_type {
+            PhysicalType::BOOLEAN => {
+                let (byte, bit) = (values.len() / 8, values.len() % 8);
+                match buf.get(byte) {
+                    Some(b) => values.push((b >> bit & 1 == 1).to_string()),
+                    None => break,
+                }
+                continue;
+            }
+            PhysicalType::INT32 | PhysicalType::FLOAT => 4,
+            PhysicalType::INT64 | PhysicalType::DOUBLE => 8,
+            PhysicalType::INT96 => 12,
+            PhysicalType::FIXED_LEN_BYTE_ARRAY => type_length.max(0) as usize,
+            PhysicalType::BYTE_ARRAY => {
+                if buf.len() < 4 {
+                    break;
+                }
+                buf.get_u32_le() as usize
+            }
+        };
+        if buf.len() < width {
+            break;
+        }
+        let (value, rest) = buf.split_at(width);
+        values.push(match physical_type {
+            PhysicalType::INT32 => i32::from_le_bytes(value.try_into().unwrap()).to_string(),
+            PhysicalType::INT64 => i64::from_le_bytes(value.try_into().unwrap()).to_string(),
+            PhysicalType::FLOAT => f32::from_le_bytes(value.try_into().unwrap()).to_string(),
+            PhysicalType::DOUBLE => f64::from_le_bytes(value.try_into().unwrap()).to_string(),
+            _ => bytes_to_string(value),
+        });
+        buf = rest;
+    }
+    values
+}
+
+fn bytes_to_string(value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        Ok(s) => format!("'{}'", s),
+        Err(_) => {
+            let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("0x{}", hex)
+        }
+    }
+}
+
+// A column chunk read into memory and addressed by file offsets, so the page reader
+// of the parquet crate can decompress its pages
+pub(crate) struct ChunkBytes {
+    pub(crate) start: u64,
+    pub(crate) data: Bytes,
+}
+
+impl ChunkBytes {
+    fn slice(&self, start: u64, length: usize) -> parquet::errors::Result<Bytes> {
+        let from = start
+            .checked_sub(self.start)
+            .map(|from| from as usize)
+            .filter(|from| from + length <= self.data.len())
+            .ok_or_else(|| {
+                ParquetError::EOF(format!("Offset {} is outside the column chunk", start))
+            })?;
+        Ok(self.data.slice(from..from + length))
+    }
+}
+
+impl Length for ChunkBytes {
+    fn len(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+}
+
+impl ChunkReader for ChunkBytes {
+    type T = bytes::buf::Reader<Bytes>;
+
+    fn get_read(&self, start: u64) -> parquet::errors::Result<Self::T> {
+        let length = (self.len().saturating_sub(start)) as usize;
+        Ok(self.slice(start, length)?.reader())
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> parquet::errors::Result<Bytes> {
+        self.slice(start, length)
+    }
+}
+
+// The fields of a page header shown in the page list
+pub(crate) struct PageHeader {
+    pub(crate) page_type: &'static str,
+    pub(crate) uncompressed_size: i64,
+    pub(crate) compressed_size: i64,
+    pub(crate) crc: Option<i64>,
+    pub(crate) values: Option<i64>,
+    pub(crate) nulls: Option<i64>,
+    pub(crate) rows: Option<i64>,
+    pub(crate) encoding: String,
+}
+
+impl PageHeader {
+    // Field ids are those of PageHeader and the page type headers in parquet.thrift
+    fn new(fields: &[(i16, Thrift)]) -> Self {
+        let page_type = match int_field(fields, 1) {
+            Some(0) => "DATA_PAGE",
+            Some(1) => "INDEX_PAGE",
+            Some(2) => "DICTIONARY_PAGE",
+            Some(3) => "DATA_PAGE_V2",
+            _ => "UNKNOWN",
+        };
+        let mut header = PageHeader {
+            page_type,
+            uncompressed_size: int_field(fields, 2).unwrap_or(0),
+            compressed_size: int_field(fields, 3).unwrap_or(0),
+            crc: int_field(fields, 4),
+            values: None,
+            nulls: None,
+            rows: None,
+            encoding: String::new(),
+        };
+        if let Some(data_page) = struct_field(fields, 5) {
+            header.values = int_field(data_page, 1);
+            // v1 pages only carry the null count in their statistics
+            header.nulls = struct_field(data_page, 5).and_then(|stats| int_field(stats, 3));
+            header.encoding = format!(
+                "{} (def {}, rep {})",
+                encoding_name(int_field(data_page, 2)),
+                encoding_name(int_field(data_page, 3)),
+                encoding_name(int_field(data_page, 4))
+            );
+        } else if let Some(dictionary_page) = struct_field(fields, 7) {
+            header.values = int_field(dictionary_page, 1);
+            header.encoding = encoding_name(int_field(dictionary_page, 2)).to_string();
+        } else if let Some(data_page) = struct_field(fields, 8) {
+            header.values = int_field(data_page, 1);
+            header.nulls = int_field(data_page, 2);
+            header.rows = int_field(data_page, 3);
+            header.encoding = encoding_name(int_field(data_page, 4)).to_string();
+        }
+        header
+    }
+}
+
+fn encoding_name(encoding: Option<i64>) -> &'static str {
+    match encoding {
+        Some(0) => "PLAIN",
+        Some(2) => "PLAIN_DICTIONARY",
+        Some(3) => "RLE",
+        Some(4) => "BIT_PACKED",
+        Some(5) => "DELTA_BINARY_PACKED",
+        Some(6) => "DELTA_LENGTH_BYTE_ARRAY",
+        Some(7) => "DELTA_BYTE_ARRAY",
+        Some(8) => "RLE_DICTIONARY",
+        Some(9) => "BYTE_STREAM_SPLIT",
+        _ => "UNKNOWN",
+    }
+}
+
+fn int_field(fields: &[(i16, Thrift)], id: i16) -> Option<i64> {
+    fields.iter().find_map(|(field_id, value)| match value {
+        Thrift::Int(n) if *field_id == id => Some(*n),
+        _ => None,
+    })
+}
+
+fn struct_field(fields: &[(i16, Thrift)], id: i16) -> Option<&[(i16, Thrift)]> {
+    fields.iter().find_map(|(field_id, value)| match value {
+        Thrift::Struct(fields) if *field_id == id => Some(fields.as_slice()),
+        _ => None,
+    })
+}
+
+// A value of the Thrift compact protocol, which page headers are serialized with.
+// Only integers and structs are kept, everything else is skipped over.
+enum Thrift {
+    Int(i64),
+    Struct(Vec<(i16, Thrift)>),
+    Other,
+}
+
+struct CompactReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CompactReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.buf.get(self.pos).ok_or("unexpected end of data")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint is too long".to_string())
+    }
+
+    fn zigzag(&mut self) -> Result<i64, String> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    // Fields by id until the stop field; booleans are folded into the field type
+    fn read_struct(&mut self) -> Result<Vec<(i16, Thrift)>, String> {
+        let mut fields = vec![];
+        let mut last_id = 0i16;
+        loop {
+            let header = self.byte()?;
+            if header == 0 {
+                return Ok(fields);
+            }
+            let id = match header >> 4 {
+                0 => self.zigzag()? as i16,
+                delta => last_id + delta as i16,
+            };
+            last_id = id;
+            let value = match header & 0x0f {
+                1 | 2 => Thrift::Other,
+                field_type => self.value(field_type)?,
+            };
+            fields.push((id, value));
+        }
+    }
+
+    fn value(&mut self, value_type: u8) -> Result<Thrift, String> {
+        Ok(match value_type {
+            // booleans in lists take a byte each
+            1 | 2 => {
+                self.skip(1)?;
+                Thrift::Other
+            }
+            3 => Thrift::Int(self.byte()? as i8 as i64),
+            4..=6 => Thrift::Int(self.zigzag()?),
+            7 => {
+                self.skip(8)?;
+                Thrift::Other
+            }
+            8 => {
+                let len = self.varint()? as usize;
+                self.skip(len)?;
+                Thrift::Other
+            }
+            9 | 10 => {
+                let header = self.byte()?;
+                let size = match header >> 4 {
+                    15 => self.varint()?,
+                    size => size as u64,
+                };
+                for _ in 0..size {
+                    self.value(header & 0x0f)?;
+                }
+                Thrift::Other
+            }
+            11 => {
+                let size = self.varint()?;
+                if size > 0 {
+                    let types = self.byte()?;
+                    for _ in 0..size {
+                        self.value(types >> 4)?;
+                        self.value(types & 0x0f)?;
+                    }
+                }
+                Thrift::Other
+            }
+            12 => Thrift::Struct(self.read_struct()?),
+            _ => return Err(format!("unknown field type {}", value_type)),
+        })
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        if self.buf.len() - self.pos < len {
+            return Err("unexpected end of data".to_string());
+        }
+        self.pos += len;
+        Ok(())
+    }
+}
@@ -1,69 +1,1220 @@
-use std::{error::Error, fs::File};
+use std::error::Error;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 
-use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use arrow::array::{Array, ArrayRef, AsArray, RecordBatch, RecordBatchOptions, StringArray};
+use arrow::compute::kernels::cmp::lt_eq;
+use arrow::compute::{CastOptions, cast_with_options};
+use arrow::datatypes::{
+    DataType, Decimal128Type, Field, Float32Type, Float64Type, Int32Type, Int64Type, Schema,
+    SchemaRef, UInt32Type, UInt64Type,
+};
+use futures::TryStreamExt;
+use parquet::arrow::arrow_reader::statistics::StatisticsConverter;
+use parquet::arrow::arrow_reader::{
+    ArrowPredicateFn, ArrowReaderOptions, RowFilter, RowSelection, RowSelector,
+};
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::arrow::{
+    ParquetRecordBatchStreamBuilder, ProjectionMask, parquet_to_arrow_schema,
+    parquet_to_arrow_schema_by_columns,
+};
+use parquet::basic::{ConvertedType, LogicalType, Type as PhysicalType};
+use parquet::bloom_filter::Sbbf;
+use parquet::data_type::ByteArray;
+use parquet::file::metadata::{ColumnChunkMetaData, ParquetMetaData};
+use parquet::schema::types::{ColumnDescriptor, SchemaDescriptor, Type};
 
-use crate::utils::print_rows;
+use crate::encryption::{Keys, describe_columns, masked_columns};
+use crate::predicate::Predicate;
+use crate::render::{NestedModes, render_batch};
+use crate::topn::{OrderBy, top_rows};
+use crate::utils::{
+    compute_col_max_len, human_size, print_records, print_rows, print_rows_left, table_width,
+};
 
-pub fn parquet_view_from_slice(buffer: &[u8], max_rows: usize) -> Result<(), Box<dyn Error>> {
-    let temp_file_path = "temp_view_file.parquet";
-    std::fs::write(temp_file_path, buffer).unwrap();
-    crate::pqt::parquet_view(temp_file_path.to_string(), max_rows)?;
+// Longer key/value metadata (e.g. the embedded ARROW:schema) is cut to this many characters
+const MAX_METADATA_VALUE_LEN: usize = 80;
 
-    let _ = std::fs::remove_file(temp_file_path);
-    Ok(())
+// Shown for the values of encrypted columns without a key
+pub(crate) const MASK: &str = "*****";
+
+// Rows shown by `view` when no row count is given
+pub const DEFAULT_MAX_ROWS: usize = 20;
+
+// How `view` lays out rows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisplayMode {
+    // a table, unless it is wider than the terminal
+    Auto,
+    Table,
+    // one `column | value` block per row
+    Vertical,
+}
+
+// What to show from a Parquet file
+#[derive(Clone, Debug)]
+pub struct ViewOptions {
+    // rows skipped before the first one shown
+    pub offset: usize,
+    pub max_rows: usize,
+    // only these ranges of row groups are read, all of them when not set
+    pub row_groups: Option<Vec<RangeInclusive<usize>>>,
+    // column names or glob patterns, e.g. price_*; all columns when not set
+    pub columns: Option<Vec<String>>,
+    // only rows matching this are shown
+    pub filter: Option<Predicate>,
+    // how nested columns are displayed, flattened unless set otherwise
+    pub nested: NestedModes,
+    // the console's display mode is used when not set
+    pub display: Option<DisplayMode>,
+    // columns of the terminal, when known, for DisplayMode::Auto
+    pub terminal_width: Option<usize>,
+    // encrypted columns these can't decrypt are shown masked
+    pub keys: Keys,
+    // the path is a directory or glob of files viewed as one table
+    pub dataset: bool,
+    // the top rows by these columns are shown instead of rows in file order
+    pub order_by: Vec<OrderBy>,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            max_rows: DEFAULT_MAX_ROWS,
+            row_groups: None,
+            columns: None,
+            filter: None,
+            nested: vec![],
+            display: None,
+            terminal_width: None,
+            keys: Keys::default(),
+            dataset: false,
+            order_by: vec![],
+        }
+    }
+}
+
+// Which rows `view` showed, so the console can page further
+#[derive(Clone, Copy, Debug)]
+pub struct ViewPage {
+    pub offset: usize,
+    pub rows: usize,
+    // rows in the file, None when a filter makes the number of matching rows unknown
+    pub total: Option<usize>,
+}
+
+// Rows of a file, and of those the ones matching a filter when one was given
+#[derive(Clone, Copy, Debug)]
+pub struct RowCount {
+    pub row_groups: usize,
+    pub rows: usize,
+    pub matching: Option<usize>,
+}
+
+// Wrap an in-memory Parquet file so it can be decoded without touching the disk
+pub fn reader_from_bytes(content: Vec<u8>) -> Box<dyn AsyncFileReader> {
+    Box::new(std::io::Cursor::new(content))
+}
+
+// View a Parquet file through any seekable source: a local file, a remote object
+// fetched by ranges or bytes already in memory. Only the footer and the row groups
+// holding the requested rows are read, and of those only the selected column chunks.
+pub async fn parquet_view<T: AsyncFileReader + Unpin + Send + 'static>(
+    reader: T,
+    options: &ViewOptions,
+) -> Result<ViewPage, Box<dyn Error>> {
+    // The offset index lets the reader skip whole pages in front of the first row
+    let page_index = options.filter.is_some() || options.offset > 0;
+    let reader_options = ArrowReaderOptions::new().with_page_index(page_index);
+    let mut builder =
+        ParquetRecordBatchStreamBuilder::new_with_options(reader, reader_options).await?;
+    let metadata = builder.metadata().clone();
+    let row_groups = match &options.row_groups {
+        Some(ranges) => {
+            let row_groups = selected_row_groups(&metadata, ranges)?;
+            print_row_group_ranges(&metadata, &row_groups);
+            row_groups
+        }
+        None => (0..metadata.num_row_groups()).collect(),
+    };
+    let num_rows: usize = row_groups
+        .iter()
+        .map(|rg| metadata.row_group(*rg).num_rows() as usize)
+        .sum();
+    let schema_descr = builder.parquet_schema().clone();
+    // The columns ordered by are shown even when --columns leaves them out
+    let columns = options.columns.as_ref().map(|columns| {
+        let mut columns = columns.clone();
+        for order in options.order_by.iter() {
+            if !columns.contains(&order.column) {
+                columns.push(order.column.clone());
+            }
+        }
+        columns
+    });
+    let mut leaves = match &columns {
+        Some(columns) => projected_leaves(&schema_descr, columns)?,
+        None => (0..schema_descr.num_columns()).collect(),
+    };
+    // Top level columns with any leaf that can't be decrypted aren't read, but shown masked
+    let masked: Vec<String> = masked_columns(&metadata, &options.keys)
+        .into_iter()
+        .filter(|idx| leaves.contains(idx))
+        .map(|idx| schema_descr.column(idx).path().parts()[0].clone())
+        .collect();
+    if !masked.is_empty() {
+        leaves.retain(|idx| !masked.contains(&schema_descr.column(*idx).path().parts()[0]));
+        println!("Masked, no key to decrypt them: {}", masked.join(", "));
+    }
+    if options.columns.is_some() || !masked.is_empty() {
+        builder = builder.with_projection(ProjectionMask::leaves(&schema_descr, leaves));
+    }
+    let schema = builder.schema().clone();
+    let mut batches: Vec<RecordBatch> = if options.order_by.is_empty() {
+        builder = match &options.filter {
+            // Matching rows can only be counted by decoding, so the offset is applied after the filter
+            Some(filter) => apply_filter(builder, filter, &row_groups)?.with_offset(options.offset),
+            None => select_rows(builder, &row_groups, options.offset, options.max_rows),
+        };
+        let stream = builder
+            .with_batch_size(8192)
+            .with_limit(options.max_rows)
+            .build()?;
+        stream.try_collect().await?
+    } else {
+        let count = options.offset.saturating_add(options.max_rows);
+        let top = top_rows(
+            builder,
+            &options.order_by,
+            &row_groups,
+            options.filter.as_ref(),
+            count,
+        )
+        .await?;
+        top.into_iter()
+            .map(|batch| {
+                let skip = options.offset.min(batch.num_rows());
+                batch.slice(skip, batch.num_rows() - skip)
+            })
+            .filter(|batch| batch.num_rows() > 0)
+            .collect()
+    };
+    if !masked.is_empty() {
+        batches = batches
+            .iter()
+            .map(|batch| mask_columns(batch, &schema, &masked))
+            .collect::<Result<_, _>>()?;
+    }
+
+    let page = ViewPage {
+        offset: options.offset,
+        rows: batches.iter().map(|b| b.num_rows()).sum(),
+        total: options.filter.is_none().then_some(num_rows),
+    };
+    if page.rows == 0 {
+        match page.total {
+            Some(total) => println!(
+                "No rows at offset {}, the file has {} rows",
+                page.offset, total
+            ),
+            None => println!("No matching rows at offset {}", page.offset),
+        }
+        return Ok(page);
+    }
+    print_batches(&batches, options);
+    let (first, last) = (page.offset + 1, page.offset + page.rows);
+    match page.total {
+        Some(total) => println!("rows {}\u{2013}{} of {}", first, last, total),
+        None => println!(
+            "matching rows {}\u{2013}{} ({} rows read)",
+            first, last, num_rows
+        ),
+    }
+    Ok(page)
+}
+
+// The row groups in the given ranges, each once in the order given, failing on ones
+// the file doesn't have
+fn selected_row_groups(
+    metadata: &ParquetMetaData,
+    ranges: &[RangeInclusive<usize>],
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut seen = vec![false; metadata.num_row_groups()];
+    let mut row_groups = vec![];
+    for range in ranges {
+        if *range.end() >= metadata.num_row_groups() {
+            return Err(format!(
+                "Row group {} doesn't exist, the file has {} row groups",
+                range.end(),
+                metadata.num_row_groups()
+            )
+            .into());
+        }
+        for idx in range.clone() {
+            if !seen[idx] {
+                seen[idx] = true;
+                row_groups.push(idx);
+            }
+        }
+    }
+    Ok(row_groups)
+}
+
+// Print where in the file each of the selected row groups lies
+fn print_row_group_ranges(metadata: &ParquetMetaData, row_groups: &[usize]) {
+    let mut first_rows = vec![0];
+    for rg in metadata.row_groups() {
+        first_rows.push(first_rows[first_rows.len() - 1] + rg.num_rows());
+    }
+    for idx in row_groups {
+        let rg = metadata.row_group(*idx);
+        println!(
+            "Row group {}: rows {}\u{2013}{} of {}, {} compressed, {} uncompressed",
+            idx,
+            first_rows[*idx] + 1,
+            first_rows[*idx + 1],
+            metadata.file_metadata().num_rows(),
+            human_size(rg.compressed_size()),
+            human_size(rg.total_byte_size())
+        );
+    }
 }
 
-pub fn parquet_view(path: String, max_rows: usize) -> Result<(), Box<dyn Error>> {
-    let file = File::open(&path)?;
-    let parquet_reader = ParquetRecordBatchReaderBuilder::try_new(file)?
+// Read only those of the given row groups holding rows [offset, offset + limit), found
+// from their row counts. Rows in front of the offset are skipped by a row selection, which the offset
+// index turns into skipping whole pages instead of decoding them.
+pub(crate) fn select_rows<T>(
+    builder: ParquetRecordBatchStreamBuilder<T>,
+    row_groups: &[usize],
+    offset: usize,
+    limit: usize,
+) -> ParquetRecordBatchStreamBuilder<T> {
+    let end = offset.saturating_add(limit);
+    let mut selected = vec![];
+    let mut selectors = vec![];
+    let mut first_row = 0;
+    for idx in row_groups {
+        let rg_rows = builder.metadata().row_group(*idx).num_rows() as usize;
+        if first_row < end && offset < first_row + rg_rows {
+            let skip = offset.saturating_sub(first_row);
+            let select = end.min(first_row + rg_rows) - first_row - skip;
+            selected.push(*idx);
+            selectors.push(RowSelector::skip(skip));
+            selectors.push(RowSelector::select(select));
+            selectors.push(RowSelector::skip(rg_rows - skip - select));
+        }
+        first_row += rg_rows;
+    }
+    builder
+        .with_row_groups(selected)
+        .with_row_selection(RowSelection::from(selectors))
+}
+
+// Count rows from the footer alone. A filter can only be answered by decoding, so then
+// the filter columns of the row groups and pages statistics don't rule out are scanned.
+pub async fn parquet_count<T: AsyncFileReader + Unpin + Send + 'static>(
+    mut reader: T,
+    filter: Option<&Predicate>,
+) -> Result<RowCount, Box<dyn Error>> {
+    let Some(filter) = filter else {
+        let metadata = read_metadata(&mut reader, false).await?;
+        return Ok(RowCount {
+            row_groups: metadata.num_row_groups(),
+            rows: metadata.file_metadata().num_rows() as usize,
+            matching: None,
+        });
+    };
+
+    let reader_options = ArrowReaderOptions::new().with_page_index(true);
+    let builder = ParquetRecordBatchStreamBuilder::new_with_options(reader, reader_options).await?;
+    let metadata = builder.metadata().clone();
+    let mask = projection_mask(builder.parquet_schema(), &filter.columns())?;
+    let row_groups: Vec<usize> = (0..metadata.num_row_groups()).collect();
+    let mut stream = apply_filter(builder.with_projection(mask), filter, &row_groups)?
         .with_batch_size(8192)
-        .with_limit(10)
         .build()?;
+    let mut matching = 0;
+    while let Some(batch) = stream.try_next().await? {
+        matching += batch.num_rows();
+    }
+    Ok(RowCount {
+        row_groups: metadata.num_row_groups(),
+        rows: metadata.file_metadata().num_rows() as usize,
+        matching: Some(matching),
+    })
+}
+
+// Skip those of the given row groups and their pages that statistics rule out, and
+// filter the remaining rows
+pub(crate) fn apply_filter<T>(
+    builder: ParquetRecordBatchStreamBuilder<T>,
+    filter: &Predicate,
+    candidates: &[usize],
+) -> Result<ParquetRecordBatchStreamBuilder<T>, Box<dyn Error>> {
+    let metadata = builder.metadata().clone();
+    let arrow_schema = builder.schema().clone();
 
-    let mut batches = Vec::new();
+    let keep = filter.prune_row_groups(&metadata, &arrow_schema)?;
+    let row_groups: Vec<usize> = candidates.iter().copied().filter(|i| keep[*i]).collect();
+    println!(
+        "Skipped {} of {} row groups using statistics",
+        candidates.len() - row_groups.len(),
+        candidates.len()
+    );
+    filter_rows(
+        builder.with_row_groups(row_groups.clone()),
+        filter,
+        &row_groups,
+    )
+}
 
-    for batch in parquet_reader {
-        batches.push(batch?);
+// Skip the pages of the row groups read that the page index rules out, and filter the
+// remaining rows
+pub(crate) fn filter_rows<T>(
+    mut builder: ParquetRecordBatchStreamBuilder<T>,
+    filter: &Predicate,
+    row_groups: &[usize],
+) -> Result<ParquetRecordBatchStreamBuilder<T>, Box<dyn Error>> {
+    let metadata = builder.metadata().clone();
+    let arrow_schema = builder.schema().clone();
+    if let Some(selection) = filter.page_selection(&metadata, &arrow_schema, row_groups)? {
+        println!(
+            "Skipped {} of {} rows using the page index",
+            selection.skipped_row_count(),
+            selection.row_count() + selection.skipped_row_count()
+        );
+        builder = builder.with_row_selection(selection);
     }
+    with_row_filter(builder, filter)
+}
 
-    let mut rows = vec![];
-    let mut col_max_len = vec![];
-    for batch in batches.iter() {
-        let mut i = 0;
-        loop {
-            let mut row = vec![];
-            if i >= batch.num_rows() {
-                break;
-            }
-            if i > max_rows {
-                break;
+// Filter the rows read by the predicate
+pub(crate) fn with_row_filter<T>(
+    builder: ParquetRecordBatchStreamBuilder<T>,
+    filter: &Predicate,
+) -> Result<ParquetRecordBatchStreamBuilder<T>, Box<dyn Error>> {
+    let mask = projection_mask(builder.parquet_schema(), &filter.columns())?;
+    let predicate = filter.clone();
+    let row_filter = RowFilter::new(vec![Box::new(ArrowPredicateFn::new(mask, move |batch| {
+        predicate.evaluate(&batch)
+    }))]);
+    Ok(builder.with_row_filter(row_filter))
+}
+
+// Put the masked columns back in their place among the columns read, with every value
+// shown as asterisks
+fn mask_columns(
+    batch: &RecordBatch,
+    schema: &SchemaRef,
+    masked: &[String],
+) -> Result<RecordBatch, Box<dyn Error>> {
+    let mut fields = vec![];
+    let mut columns = vec![];
+    for field in schema.fields() {
+        if masked.contains(field.name()) {
+            let values = StringArray::from(vec![MASK; batch.num_rows()]);
+            fields.push(Field::new(field.name(), DataType::Utf8, false));
+            columns.push(Arc::new(values) as ArrayRef);
+        } else if let Some(column) = batch.column_by_name(field.name()) {
+            fields.push(field.as_ref().clone());
+            columns.push(column.clone());
+        }
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+    Ok(RecordBatch::try_new_with_options(
+        Arc::new(Schema::new(fields)),
+        columns,
+        &options,
+    )?)
+}
+
+// Select leaf columns whose path, or any parent group of it, matches one of the
+// names or glob patterns, so `address` and `address.*` both pick a whole struct
+pub(crate) fn projection_mask(
+    schema_descr: &SchemaDescriptor,
+    columns: &[String],
+) -> Result<ProjectionMask, Box<dyn Error>> {
+    let leaves = projected_leaves(schema_descr, columns)?;
+    Ok(ProjectionMask::leaves(schema_descr, leaves))
+}
+
+fn projected_leaves(
+    schema_descr: &SchemaDescriptor,
+    columns: &[String],
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut leaves = vec![];
+    for column in columns {
+        let pattern = glob::Pattern::new(column)?;
+        let mut matched = false;
+        for (idx, descr) in schema_descr.columns().iter().enumerate() {
+            let parts = descr.path().parts();
+            let selected = (1..=parts.len()).any(|n| pattern.matches(&parts[..n].join(".")));
+            if selected {
+                matched = true;
+                if !leaves.contains(&idx) {
+                    leaves.push(idx);
+                }
             }
-            let mut colno = 0;
-            for f in batch.schema().fields() {
-                let name = f.name().clone();
-                if i == 0 {
-                    let l = name.len();
-                    row.push(name);
-                    col_max_len.push(l);
+        }
+        if !matched {
+            return Err(format!("No column matches {}", column).into());
+        }
+    }
+    Ok(leaves)
+}
+
+// Read just the footer, optionally together with the column and offset indexes
+pub async fn read_metadata<T: AsyncFileReader>(
+    reader: &mut T,
+    page_index: bool,
+) -> Result<Arc<ParquetMetaData>, Box<dyn Error>> {
+    let options = ArrowReaderOptions::new().with_page_index(page_index);
+    Ok(reader.get_metadata(Some(&options)).await?)
+}
+
+// Print the Parquet message type as a tree next to the Arrow schema derived from it
+pub async fn parquet_schema<T: AsyncFileReader>(mut reader: T) -> Result<(), Box<dyn Error>> {
+    let metadata = read_metadata(&mut reader, false).await?;
+    let file_metadata = metadata.file_metadata();
+    let schema_descr = file_metadata.schema_descr();
+    let arrow_schema = parquet_to_arrow_schema(schema_descr, file_metadata.key_value_metadata())?;
+
+    let mut rows = vec![vec![
+        "Parquet field".to_string(),
+        "Physical type".to_string(),
+        "Logical type".to_string(),
+        "Converted type".to_string(),
+        "Repetition".to_string(),
+        "Field id".to_string(),
+        "Arrow type".to_string(),
+    ]];
+    let root = schema_descr.root_schema();
+    rows.push(vec![
+        format!("message {}", root.name()),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+    ]);
+    let arrow_fields: Vec<&Field> = arrow_schema.fields().iter().map(|f| f.as_ref()).collect();
+    for field in root.get_fields() {
+        let arrow_field = find_arrow_field(&arrow_fields, field.name(), root.get_fields().len());
+        let arrow_children = arrow_field.map(arrow_child_fields).unwrap_or_default();
+        schema_tree_rows(field, arrow_field, arrow_children, 1, &mut rows);
+    }
+
+    let col_max_len = compute_col_max_len(&rows);
+    print_rows_left(&rows, &col_max_len, true);
+    Ok(())
+}
+
+fn schema_tree_rows(
+    node: &Type,
+    arrow_field: Option<&Field>,
+    arrow_children: Vec<&Field>,
+    depth: usize,
+    rows: &mut Vec<Vec<String>>,
+) {
+    let info = node.get_basic_info();
+    let indent = "  ".repeat(depth);
+    let (name, physical_type) = if node.is_group() {
+        (format!("{indent}{}/", info.name()), "group".to_string())
+    } else {
+        let physical_type = match node.get_physical_type() {
+            PhysicalType::FIXED_LEN_BYTE_ARRAY => {
+                if let Type::PrimitiveType { type_length, .. } = node {
+                    format!("FIXED_LEN_BYTE_ARRAY({type_length})")
                 } else {
-                    if let Some(column) = batch.column_by_name(&name) {
-                        let value =
-                            arrow::util::display::array_value_to_string(column.as_ref(), i - 1)
-                                .expect("can't display value");
-                        let l = value.len();
-                        row.push(value);
-                        if colno < col_max_len.len() && col_max_len[colno] < l {
-                            col_max_len[colno] = l;
-                        }
-                        colno += 1;
-                    }
+                    "FIXED_LEN_BYTE_ARRAY".to_string()
                 }
             }
-            i += 1;
-            rows.push(row);
+            physical_type => physical_type.to_string(),
+        };
+        (format!("{indent}{}", info.name()), physical_type)
+    };
+    let logical_type = info
+        .logical_type_ref()
+        .map(|t| format!("{t:?}"))
+        .unwrap_or_default();
+    let converted_type = match info.converted_type() {
+        ConvertedType::NONE => String::new(),
+        converted_type => converted_type.to_string(),
+    };
+    let repetition = if info.has_repetition() {
+        info.repetition().to_string()
+    } else {
+        String::new()
+    };
+    let field_id = if info.has_id() {
+        info.id().to_string()
+    } else {
+        String::new()
+    };
+    let arrow_type = arrow_field
+        .map(|f| {
+            if f.is_nullable() {
+                f.data_type().to_string()
+            } else {
+                format!("{} not null", f.data_type())
+            }
+        })
+        .unwrap_or_default();
+    rows.push(vec![
+        name,
+        physical_type,
+        logical_type,
+        converted_type,
+        repetition,
+        field_id,
+        arrow_type,
+    ]);
+
+    if !node.is_group() {
+        return;
+    }
+    let is_list = info.converted_type() == ConvertedType::LIST
+        || matches!(info.logical_type_ref(), Some(LogicalType::List));
+    let children = node.get_fields();
+    for child in children {
+        if is_list && child.is_group() && child.get_fields().len() == 1 {
+            // the repeated wrapper of a list has no Arrow counterpart, its element is the list item
+            schema_tree_rows(child, None, arrow_children.clone(), depth + 1, rows);
+        } else {
+            let arrow_child = find_arrow_field(&arrow_children, child.name(), children.len());
+            let grandchildren = arrow_child.map(arrow_child_fields).unwrap_or_default();
+            schema_tree_rows(child, arrow_child, grandchildren, depth + 1, rows);
+        }
+    }
+}
+
+// Nested Arrow fields that correspond to the children of a Parquet group
+fn arrow_child_fields(field: &Field) -> Vec<&Field> {
+    match field.data_type() {
+        DataType::Struct(fields) => fields.iter().map(|f| f.as_ref()).collect(),
+        DataType::List(item)
+        | DataType::LargeList(item)
+        | DataType::ListView(item)
+        | DataType::LargeListView(item)
+        | DataType::FixedSizeList(item, _)
+        | DataType::Map(item, _) => vec![item.as_ref()],
+        _ => vec![],
+    }
+}
+
+// Parquet list and map wrappers name their children differently than Arrow does,
+// fall back to the position when a group has a single child on both sides
+fn find_arrow_field<'a>(fields: &[&'a Field], name: &str, siblings: usize) -> Option<&'a Field> {
+    fields
+        .iter()
+        .find(|f| f.name() == name)
+        .or_else(|| {
+            if siblings == 1 && fields.len() == 1 {
+                fields.first()
+            } else {
+                None
+            }
+        })
+        .copied()
+}
+
+// Print how the file was written: footer summary, row groups and column chunks.
+// In detail mode every column chunk of every row group is listed.
+pub async fn parquet_meta<T: AsyncFileReader>(
+    mut reader: T,
+    detail: bool,
+) -> Result<(), Box<dyn Error>> {
+    let metadata = read_metadata(&mut reader, false).await?;
+    let file_metadata = metadata.file_metadata();
+
+    let compressed: i64 = metadata
+        .row_groups()
+        .iter()
+        .map(|rg| rg.compressed_size())
+        .sum();
+    let uncompressed: i64 = metadata
+        .row_groups()
+        .iter()
+        .map(|rg| rg.total_byte_size())
+        .sum();
+    let mut rows = vec![
+        vec!["Property".to_string(), "Value".to_string()],
+        vec![
+            "created_by".to_string(),
+            file_metadata.created_by().unwrap_or_default().to_string(),
+        ],
+        vec![
+            "format version".to_string(),
+            file_metadata.version().to_string(),
+        ],
+        vec!["rows".to_string(), file_metadata.num_rows().to_string()],
+        vec![
+            "row groups".to_string(),
+            metadata.num_row_groups().to_string(),
+        ],
+        vec![
+            "columns".to_string(),
+            file_metadata.schema_descr().num_columns().to_string(),
+        ],
+        vec!["compressed size".to_string(), human_size(compressed)],
+        vec!["uncompressed size".to_string(), human_size(uncompressed)],
+    ];
+    let encrypted = describe_columns(&metadata);
+    if !encrypted.is_empty() {
+        rows.push(vec!["encrypted columns".to_string(), encrypted.join(", ")]);
+    }
+    for kv in file_metadata.key_value_metadata().into_iter().flatten() {
+        let value = kv.value.clone().unwrap_or_default();
+        let value = if value.len() > MAX_METADATA_VALUE_LEN {
+            let cut = (0..=MAX_METADATA_VALUE_LEN)
+                .rev()
+                .find(|i| value.is_char_boundary(*i))
+                .unwrap_or(0);
+            format!("{}... ({} bytes)", &value[..cut], value.len())
+        } else {
+            value
+        };
+        rows.push(vec![format!("key {}", kv.key), value]);
+    }
+    let col_max_len = compute_col_max_len(&rows);
+    print_rows_left(&rows, &col_max_len, true);
+    println!();
+
+    let mut rows = vec![vec![
+        "Row group".to_string(),
+        "Rows".to_string(),
+        "Compressed".to_string(),
+        "Uncompressed".to_string(),
+        "Offset".to_string(),
+    ]];
+    for (i, rg) in metadata.row_groups().iter().enumerate() {
+        rows.push(vec![
+            i.to_string(),
+            rg.num_rows().to_string(),
+            human_size(rg.compressed_size()),
+            human_size(rg.total_byte_size()),
+            rg.file_offset()
+                .or_else(|| rg.columns().first().map(|c| c.byte_range().0 as i64))
+                .map(|o| o.to_string())
+                .unwrap_or_default(),
+        ]);
+    }
+    let col_max_len = compute_col_max_len(&rows);
+    print_rows(&rows, &col_max_len, true);
+    println!();
+
+    if detail {
+        for (i, rg) in metadata.row_groups().iter().enumerate() {
+            println!("Row group {}: {} rows", i, rg.num_rows());
+            let mut rows = vec![column_chunk_header(true)];
+            for column in rg.columns() {
+                rows.push(column_chunk_row(
+                    column.column_path().string(),
+                    column,
+                    &[column],
+                    true,
+                ));
+            }
+            let col_max_len = compute_col_max_len(&rows);
+            print_rows(&rows, &col_max_len, true);
+            println!();
+        }
+    } else {
+        // Column chunks summed up over all row groups
+        let mut rows = vec![column_chunk_header(false)];
+        let num_columns = file_metadata.schema_descr().num_columns();
+        for colno in 0..num_columns {
+            let chunks: Vec<&ColumnChunkMetaData> = metadata
+                .row_groups()
+                .iter()
+                .map(|rg| rg.column(colno))
+                .collect();
+            if let Some(first) = chunks.first() {
+                rows.push(column_chunk_row(
+                    first.column_path().string(),
+                    first,
+                    &chunks,
+                    false,
+                ));
+            }
+        }
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows(&rows, &col_max_len, true);
+    }
+    Ok(())
+}
+
+fn column_chunk_header(detail: bool) -> Vec<String> {
+    let mut header = vec![
+        "Column".to_string(),
+        "Type".to_string(),
+        "Codec".to_string(),
+        "Encodings".to_string(),
+        "Values".to_string(),
+        "Compressed".to_string(),
+        "Uncompressed".to_string(),
+        "Ratio".to_string(),
+        "Bloom filter".to_string(),
+    ];
+    if detail {
+        header.push("Data page offset".to_string());
+        header.push("Dictionary page offset".to_string());
+    }
+    header
+}
+
+// One row describing `chunks` of the same column, `first` provides the static properties
+fn column_chunk_row(
+    name: String,
+    first: &ColumnChunkMetaData,
+    chunks: &[&ColumnChunkMetaData],
+    detail: bool,
+) -> Vec<String> {
+    let mut codecs: Vec<String> = vec![];
+    let mut encodings: Vec<String> = vec![];
+    for chunk in chunks {
+        let codec = chunk.compression().to_string();
+        if !codecs.contains(&codec) {
+            codecs.push(codec);
+        }
+        for encoding in chunk.encodings() {
+            let encoding = encoding.to_string();
+            if !encodings.contains(&encoding) {
+                encodings.push(encoding);
+            }
         }
     }
+    let values: i64 = chunks.iter().map(|c| c.num_values()).sum();
+    let compressed: i64 = chunks.iter().map(|c| c.compressed_size()).sum();
+    let uncompressed: i64 = chunks.iter().map(|c| c.uncompressed_size()).sum();
+    let ratio = if compressed > 0 {
+        format!("{:.2}", uncompressed as f64 / compressed as f64)
+    } else {
+        String::new()
+    };
+    // Older writers don't record the length, only where the filter starts
+    let bloom_filters = chunks
+        .iter()
+        .filter(|c| c.bloom_filter_offset().is_some())
+        .count();
+    let bloom_filter_size: i64 = chunks
+        .iter()
+        .filter_map(|c| c.bloom_filter_length())
+        .map(i64::from)
+        .sum();
+    let bloom_filter = match bloom_filters {
+        0 => "none".to_string(),
+        _ if bloom_filter_size == 0 => "yes".to_string(),
+        n if n < chunks.len() => format!("{} in {} row groups", human_size(bloom_filter_size), n),
+        _ => human_size(bloom_filter_size),
+    };
+    let mut row = vec![
+        name,
+        first.column_type().to_string(),
+        codecs.join(","),
+        encodings.join(","),
+        values.to_string(),
+        human_size(compressed),
+        human_size(uncompressed),
+        ratio,
+        bloom_filter,
+    ];
+    if detail {
+        row.push(first.data_page_offset().to_string());
+        row.push(
+            first
+                .dictionary_page_offset()
+                .map(|o| o.to_string())
+                .unwrap_or_default(),
+        );
+    }
+    row
+}
+
+// Probe the bloom filter of a column in every row group for a value. Only the filter
+// blocks are read, so remote files are fetched by small ranges.
+pub async fn parquet_bloom<T: AsyncFileReader + Unpin + Send + 'static>(
+    reader: T,
+    column: &str,
+    value: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
+    let metadata = builder.metadata().clone();
+    let schema_descr = metadata.file_metadata().schema_descr();
+    let column_idx = leaf_column(schema_descr, column)?;
+    let descr = schema_descr.column(column_idx);
+    let physical_type = descr.physical_type();
+    let data_type = leaf_data_type(
+        schema_descr,
+        column_idx,
+        metadata.file_metadata().key_value_metadata(),
+    )?;
+    let value = value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .unwrap_or(value);
+    println!(
+        "Bloom filters of column {} ({}) for value {}:",
+        column, physical_type, value
+    );
 
+    let mut rows = vec![vec![
+        "Row group".to_string(),
+        "Rows".to_string(),
+        "Bloom filter".to_string(),
+        "Result".to_string(),
+    ]];
+    let (mut candidates, mut without_filter) = (0, 0);
+    for (idx, rg) in metadata.row_groups().iter().enumerate() {
+        let chunk = rg.column(column_idx);
+        let size = chunk
+            .bloom_filter_length()
+            .map(|l| human_size(l as i64))
+            .unwrap_or_default();
+        let result = match builder
+            .get_row_group_column_bloom_filter(idx, column_idx)
+            .await?
+        {
+            None => {
+                without_filter += 1;
+                "no bloom filter"
+            }
+            Some(filter) if bloom_check(&filter, &descr, &data_type, value)? => {
+                candidates += 1;
+                "might contain"
+            }
+            Some(_) => "absent",
+        };
+        rows.push(vec![
+            idx.to_string(),
+            rg.num_rows().to_string(),
+            size,
+            result.to_string(),
+        ]);
+    }
+    let col_max_len = compute_col_max_len(&rows);
     print_rows(&rows, &col_max_len, true);
+    print!(
+        "{} might be in {} of {} row groups",
+        value,
+        candidates,
+        metadata.num_row_groups()
+    );
+    if without_filter > 0 {
+        print!(", {} row groups have no bloom filter", without_filter);
+    }
+    println!();
     Ok(())
 }
+
+// Index of a leaf column by its dotted path, e.g. address.city
+pub(crate) fn leaf_column(
+    schema_descr: &SchemaDescriptor,
+    column: &str,
+) -> Result<usize, Box<dyn Error>> {
+    (0..schema_descr.num_columns())
+        .find(|i| schema_descr.column(*i).path().string() == column)
+        .ok_or_else(|| format!("No such column: {}", column).into())
+}
+
+// The Arrow type of a leaf column, below the structs and lists it sits in
+pub(crate) fn leaf_data_type(
+    schema_descr: &SchemaDescriptor,
+    idx: usize,
+    key_value_metadata: Option<&Vec<parquet::file::metadata::KeyValue>>,
+) -> Result<DataType, Box<dyn Error>> {
+    let mask = ProjectionMask::leaves(schema_descr, [idx]);
+    let schema = parquet_to_arrow_schema_by_columns(schema_descr, mask, key_value_metadata)?;
+    let path = schema_descr.column(idx).path().parts().to_vec();
+    let mut data_type = schema.field(0).data_type().clone();
+    loop {
+        data_type = match data_type {
+            DataType::Struct(fields) => fields
+                .iter()
+                .find(|f| path.contains(f.name()))
+                .unwrap_or(&fields[0])
+                .data_type()
+                .clone(),
+            DataType::List(field)
+            | DataType::LargeList(field)
+            | DataType::FixedSizeList(field, _)
+            | DataType::Map(field, _) => field.data_type().clone(),
+            DataType::Dictionary(_, value_type) => *value_type,
+            data_type => return Ok(data_type),
+        };
+    }
+}
+
+// Bloom filters hash the plain encoding of a value, so it is parsed as the column's
+// Arrow type, e.g. a date or a decimal, and then stored the way the column stores it
+pub(crate) fn bloom_check(
+    filter: &Sbbf,
+    descr: &ColumnDescriptor,
+    data_type: &DataType,
+    value: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let invalid = || format!("{} is not a valid {} value", value, data_type);
+    let parse = |to: &DataType| -> Result<ArrayRef, String> {
+        let options = CastOptions {
+            safe: false,
+            ..Default::default()
+        };
+        cast_with_options(&StringArray::from(vec![value]), to, &options)
+            .ok()
+            .filter(|parsed| parsed.is_valid(0))
+            .ok_or_else(invalid)
+    };
+    Ok(match (descr.physical_type(), data_type) {
+        (PhysicalType::BOOLEAN, _) => {
+            filter.check(&parse(&DataType::Boolean)?.as_boolean().value(0))
+        }
+        (PhysicalType::INT32, DataType::UInt32) => {
+            filter.check(&(parse(data_type)?.as_primitive::<UInt32Type>().value(0) as i32))
+        }
+        (PhysicalType::INT64, DataType::UInt64) => {
+            filter.check(&(parse(data_type)?.as_primitive::<UInt64Type>().value(0) as i64))
+        }
+        // decimals store their unscaled value
+        (PhysicalType::INT32, DataType::Decimal128(_, _)) => {
+            let unscaled = parse(data_type)?.as_primitive::<Decimal128Type>().value(0);
+            filter.check(&i32::try_from(unscaled).map_err(|_| invalid())?)
+        }
+        (PhysicalType::INT64, DataType::Decimal128(_, _)) => {
+            let unscaled = parse(data_type)?.as_primitive::<Decimal128Type>().value(0);
+            filter.check(&i64::try_from(unscaled).map_err(|_| invalid())?)
+        }
+        (PhysicalType::FIXED_LEN_BYTE_ARRAY, DataType::Decimal128(_, _)) => {
+            let unscaled = parse(data_type)?.as_primitive::<Decimal128Type>().value(0);
+            let bytes = unscaled.to_be_bytes();
+            let length = (descr.type_length() as usize).min(bytes.len());
+            filter.check(&ByteArray::from(bytes[bytes.len() - length..].to_vec()))
+        }
+        // dates, times, timestamps and durations are stored as their integer value
+        (PhysicalType::INT32, _) => {
+            let parsed = match data_type {
+                DataType::Date64 => parse(&DataType::Date32)?,
+                data_type => parse(data_type)?,
+            };
+            let int = cast_with_options(&parsed, &DataType::Int32, &CastOptions::default())?;
+            filter.check(&int.as_primitive::<Int32Type>().value(0))
+        }
+        (PhysicalType::INT64, _) => {
+            let int = cast_with_options(
+                &parse(data_type)?,
+                &DataType::Int64,
+                &CastOptions::default(),
+            )?;
+            filter.check(&int.as_primitive::<Int64Type>().value(0))
+        }
+        (PhysicalType::FLOAT, _) => filter.check(
+            &parse(&DataType::Float32)?
+                .as_primitive::<Float32Type>()
+                .value(0),
+        ),
+        (PhysicalType::DOUBLE, _) => filter.check(
+            &parse(&DataType::Float64)?
+                .as_primitive::<Float64Type>()
+                .value(0),
+        ),
+        (PhysicalType::BYTE_ARRAY, DataType::Decimal128(_, _) | DataType::Decimal256(_, _)) => {
+            return Err("Bloom filters of variable length decimal columns can't be probed".into());
+        }
+        (PhysicalType::BYTE_ARRAY | PhysicalType::FIXED_LEN_BYTE_ARRAY, _) => {
+            filter.check(&ByteArray::from(value))
+        }
+        (PhysicalType::INT96, _) => {
+            return Err("Bloom filters of INT96 columns can't be probed".into());
+        }
+    })
+}
+
+// Print row group statistics and, when the file has a page index, page level
+// min/max for the selected columns (all columns when none are given). Only the
+// footer and the index pages are read.
+pub async fn parquet_stats<T: AsyncFileReader>(
+    mut reader: T,
+    columns: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let metadata = read_metadata(&mut reader, true).await?;
+    let file_metadata = metadata.file_metadata();
+    let schema_descr = file_metadata.schema_descr();
+    let arrow_schema = parquet_to_arrow_schema(schema_descr, file_metadata.key_value_metadata())?;
+
+    let names: Vec<String> = if columns.is_empty() {
+        arrow_schema
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect()
+    } else {
+        columns.to_vec()
+    };
+
+    let row_groups = metadata.row_groups();
+    let row_group_indices: Vec<usize> = (0..row_groups.len()).collect();
+    for name in names.iter() {
+        let field = arrow_schema
+            .field_with_name(name)
+            .map_err(|_| format!("No such column: {}", name))?;
+        if field.data_type().is_nested() {
+            println!(
+                "Column {}: nested column, statistics are only shown for primitive columns\n",
+                name
+            );
+            continue;
+        }
+        let converter = StatisticsConverter::try_new(name, &arrow_schema, schema_descr)?;
+        let Some(parquet_idx) = converter.parquet_column_index() else {
+            continue;
+        };
+        println!(
+            "Column {} ({}):",
+            name,
+            schema_descr.column(parquet_idx).physical_type()
+        );
+
+        let mins = converter.row_group_mins(row_groups.iter())?;
+        let maxes = converter.row_group_maxes(row_groups.iter())?;
+        let null_counts = converter.row_group_null_counts(row_groups.iter())?;
+        let mut rows = vec![vec![
+            "Row group".to_string(),
+            "Rows".to_string(),
+            "Min".to_string(),
+            "Max".to_string(),
+            "Nulls".to_string(),
+            "Distinct".to_string(),
+        ]];
+        for (i, rg) in row_groups.iter().enumerate() {
+            let distinct = rg
+                .column(parquet_idx)
+                .statistics()
+                .and_then(|s| s.distinct_count_opt())
+                .map(|d| d.to_string())
+                .unwrap_or_default();
+            rows.push(vec![
+                i.to_string(),
+                rg.num_rows().to_string(),
+                value_to_string(mins.as_ref(), i),
+                value_to_string(maxes.as_ref(), i),
+                value_to_string(&null_counts, i),
+                distinct,
+            ]);
+        }
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows(&rows, &col_max_len, true);
+        println!(
+            "Row groups sorted by {}: {}",
+            name,
+            sorted_label(mins.as_ref(), maxes.as_ref())?
+        );
+
+        if let (Some(column_index), Some(offset_index)) =
+            (metadata.column_index(), metadata.offset_index())
+        {
+            let page_mins =
+                converter.data_page_mins(column_index, offset_index, &row_group_indices)?;
+            let page_maxes =
+                converter.data_page_maxes(column_index, offset_index, &row_group_indices)?;
+            let page_null_counts =
+                converter.data_page_null_counts(column_index, offset_index, &row_group_indices)?;
+            let mut rows = vec![vec![
+                "Row group".to_string(),
+                "Page".to_string(),
+                "First row".to_string(),
+                "Offset".to_string(),
+                "Size".to_string(),
+                "Min".to_string(),
+                "Max".to_string(),
+                "Nulls".to_string(),
+            ]];
+            let mut page = 0;
+            for (i, rg_offsets) in offset_index.iter().enumerate() {
+                for (pageno, location) in
+                    rg_offsets[parquet_idx].page_locations().iter().enumerate()
+                {
+                    rows.push(vec![
+                        i.to_string(),
+                        pageno.to_string(),
+                        location.first_row_index.to_string(),
+                        location.offset.to_string(),
+                        human_size(location.compressed_page_size as i64),
+                        value_to_string(page_mins.as_ref(), page),
+                        value_to_string(page_maxes.as_ref(), page),
+                        value_to_string(&page_null_counts, page),
+                    ]);
+                    page += 1;
+                }
+            }
+            println!("Page index:");
+            let col_max_len = compute_col_max_len(&rows);
+            print_rows(&rows, &col_max_len, true);
+            println!(
+                "Pages sorted by {}: {}",
+                name,
+                sorted_label(page_mins.as_ref(), page_maxes.as_ref())?
+            );
+        } else {
+            println!("No page index in this file");
+        }
+        println!();
+    }
+    Ok(())
+}
+
+// Ranges given by mins and maxes are sorted when each one ends before the next one starts
+fn sorted_label(mins: &dyn Array, maxes: &dyn Array) -> Result<&'static str, Box<dyn Error>> {
+    if mins.len() < 2 {
+        return Ok("yes");
+    }
+    if mins.null_count() > 0 || maxes.null_count() > 0 {
+        return Ok("unknown (missing statistics)");
+    }
+    let n = mins.len();
+    let ordered = lt_eq(&maxes.slice(0, n - 1), &mins.slice(1, n - 1))?;
+    if ordered.true_count() == n - 1 {
+        Ok("yes")
+    } else {
+        Ok("no")
+    }
+}
+
+// Display a single array value, nulls are shown as empty cells
+pub(crate) fn value_to_string(array: &dyn Array, i: usize) -> String {
+    if i >= array.len() || array.is_null(i) {
+        return String::new();
+    }
+    arrow::util::display::array_value_to_string(array, i).unwrap_or_default()
+}
+
+pub(crate) fn print_batches(batches: &[RecordBatch], options: &ViewOptions) {
+    let mut rows: Vec<Vec<String>> = vec![];
+    for batch in batches.iter() {
+        let wanted = (options.max_rows + 1).saturating_sub(rows.len().max(1));
+        let (header, cells) = render_batch(batch, &options.nested, 0..batch.num_rows().min(wanted));
+        if rows.is_empty() {
+            rows.push(header);
+        }
+        rows.extend(cells);
+    }
+    if rows.is_empty() {
+        println!("No rows");
+        return;
+    }
+
+    let col_max_len = compute_col_max_len(&rows);
+    let vertical = match options.display.unwrap_or(DisplayMode::Auto) {
+        DisplayMode::Auto => options
+            .terminal_width
+            .is_some_and(|width| table_width(&col_max_len) > width),
+        DisplayMode::Table => false,
+        DisplayMode::Vertical => true,
+    };
+    if vertical {
+        print_records(&rows, options.offset + 1);
+    } else {
+        print_rows(&rows, &col_max_len, true);
+    }
+}
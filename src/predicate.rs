@@ -0,0 +1,1068 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, BooleanArray, Datum, Float64Array, Int64Array, RecordBatch, Scalar,
+    StringArray, StructArray, UInt64Array,
+};
+use arrow::compute::kernels::boolean::{and_kleene, not, or_kleene};
+use arrow::compute::kernels::cmp::{eq, gt, gt_eq, lt, lt_eq, neq};
+use arrow::compute::{CastOptions, cast, cast_with_options, is_not_null, is_null, like, nlike};
+use arrow::datatypes::{DataType, Schema};
+use arrow::error::ArrowError;
+use parquet::arrow::arrow_reader::statistics::StatisticsConverter;
+use parquet::arrow::arrow_reader::{RowSelection, RowSelector};
+use parquet::file::metadata::ParquetMetaData;
+
+// A row filter such as `country = 'CZ' and amount > 100`.
+//
+// Supported are comparisons (= == != <> < <= > >=) of a column with a literal,
+// `is [not] null`, `[not] in (...)`, `[not] like '...'`, `and`, `or`, `not` and
+// parentheses. Negations are pushed down to the comparisons while parsing, so the
+// tree can be checked against min/max statistics without special cases.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Compare {
+        column: String,
+        op: CompareOp,
+        value: Literal,
+    },
+    IsNull {
+        column: String,
+        negated: bool,
+    },
+    InList {
+        column: String,
+        values: Vec<Literal>,
+        negated: bool,
+    },
+    Like {
+        column: String,
+        pattern: String,
+        negated: bool,
+    },
+}
+
+// A predicate with some of its columns replaced by values, see Predicate::bind
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Bound {
+    Always,
+    Never,
+    // matching depends on the values of each row
+    Rows(Predicate),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl CompareOp {
+    fn negate(self) -> Self {
+        match self {
+            CompareOp::Eq => CompareOp::NotEq,
+            CompareOp::NotEq => CompareOp::Eq,
+            CompareOp::Lt => CompareOp::GtEq,
+            CompareOp::LtEq => CompareOp::Gt,
+            CompareOp::Gt => CompareOp::LtEq,
+            CompareOp::GtEq => CompareOp::Lt,
+        }
+    }
+
+    // The same comparison with its operands swapped, `1 < a` is `a > 1`
+    fn flip(self) -> Self {
+        match self {
+            CompareOp::Lt => CompareOp::Gt,
+            CompareOp::LtEq => CompareOp::GtEq,
+            CompareOp::Gt => CompareOp::Lt,
+            CompareOp::GtEq => CompareOp::LtEq,
+            op => op,
+        }
+    }
+}
+
+impl Literal {
+    fn to_array(&self) -> ArrayRef {
+        match self {
+            Literal::Bool(v) => Arc::new(BooleanArray::from(vec![*v])),
+            Literal::Int(v) => Arc::new(Int64Array::from(vec![*v])),
+            Literal::Float(v) => Arc::new(Float64Array::from(vec![*v])),
+            Literal::Str(v) => Arc::new(StringArray::from(vec![v.as_str()])),
+        }
+    }
+}
+
+impl Predicate {
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_tokens(tokenize(input)?)
+    }
+
+    // Parse a filter already split into tokens, e.g. the WHERE clause of a query
+    pub(crate) fn from_tokens(tokens: Vec<Token>) -> Result<Self, Box<dyn Error>> {
+        let mut parser = Parser { tokens, pos: 0 };
+        let predicate = parser.parse_or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("Unexpected {:?} in filter", token).into());
+        }
+        Ok(predicate)
+    }
+
+    fn negate(self) -> Self {
+        match self {
+            Predicate::And(a, b) => Predicate::Or(Box::new(a.negate()), Box::new(b.negate())),
+            Predicate::Or(a, b) => Predicate::And(Box::new(a.negate()), Box::new(b.negate())),
+            Predicate::Compare { column, op, value } => Predicate::Compare {
+                column,
+                op: op.negate(),
+                value,
+            },
+            Predicate::IsNull { column, negated } => Predicate::IsNull {
+                column,
+                negated: !negated,
+            },
+            Predicate::InList {
+                column,
+                values,
+                negated,
+            } => Predicate::InList {
+                column,
+                values,
+                negated: !negated,
+            },
+            Predicate::Like {
+                column,
+                pattern,
+                negated,
+            } => Predicate::Like {
+                column,
+                pattern,
+                negated: !negated,
+            },
+        }
+    }
+
+    // Columns referenced by the predicate, each one once
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns = vec![];
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns(&self, columns: &mut Vec<String>) {
+        match self {
+            Predicate::And(a, b) | Predicate::Or(a, b) => {
+                a.collect_columns(columns);
+                b.collect_columns(columns);
+            }
+            Predicate::Compare { column, .. }
+            | Predicate::IsNull { column, .. }
+            | Predicate::InList { column, .. }
+            | Predicate::Like { column, .. } => {
+                if !columns.contains(column) {
+                    columns.push(column.clone());
+                }
+            }
+        }
+    }
+
+    // Evaluate the predicate for every row of the batch, null means the row does not match
+    pub fn evaluate(&self, batch: &RecordBatch) -> Result<BooleanArray, ArrowError> {
+        match self {
+            Predicate::And(a, b) => and_kleene(&a.evaluate(batch)?, &b.evaluate(batch)?),
+            Predicate::Or(a, b) => or_kleene(&a.evaluate(batch)?, &b.evaluate(batch)?),
+            Predicate::Compare { column, op, value } => {
+                compare(&column_by_path(batch, column)?, *op, value)
+            }
+            Predicate::IsNull { column, negated } => {
+                let array = column_by_path(batch, column)?;
+                if *negated {
+                    is_not_null(&array)
+                } else {
+                    is_null(&array)
+                }
+            }
+            Predicate::InList {
+                column,
+                values,
+                negated,
+            } => {
+                let array = column_by_path(batch, column)?;
+                let op = if *negated {
+                    CompareOp::NotEq
+                } else {
+                    CompareOp::Eq
+                };
+                let mut result: Option<BooleanArray> = None;
+                for value in values {
+                    let matches = compare(&array, op, value)?;
+                    result = Some(match result {
+                        None => matches,
+                        Some(r) if *negated => and_kleene(&r, &matches)?,
+                        Some(r) => or_kleene(&r, &matches)?,
+                    });
+                }
+                Ok(result.unwrap_or_else(|| BooleanArray::from(vec![*negated; array.len()])))
+            }
+            Predicate::Like {
+                column,
+                pattern,
+                negated,
+            } => {
+                let array = cast(&column_by_path(batch, column)?, &DataType::Utf8)?;
+                let pattern = Scalar::new(StringArray::from(vec![pattern.as_str()]));
+                if *negated {
+                    nlike(&array, &pattern)
+                } else {
+                    like(&array, &pattern)
+                }
+            }
+        }
+    }
+
+    // Evaluate the parts of the predicate on columns of `known`, a batch of one row
+    // holding values that are the same for a whole file, e.g. its partition values.
+    // What is left has to be evaluated on the rows of the file.
+    pub(crate) fn bind(&self, known: &RecordBatch) -> Result<Bound, ArrowError> {
+        Ok(match self {
+            Predicate::And(a, b) => match (a.bind(known)?, b.bind(known)?) {
+                (Bound::Never, _) | (_, Bound::Never) => Bound::Never,
+                (Bound::Always, other) | (other, Bound::Always) => other,
+                (Bound::Rows(a), Bound::Rows(b)) => {
+                    Bound::Rows(Predicate::And(Box::new(a), Box::new(b)))
+                }
+            },
+            Predicate::Or(a, b) => match (a.bind(known)?, b.bind(known)?) {
+                (Bound::Always, _) | (_, Bound::Always) => Bound::Always,
+                (Bound::Never, other) | (other, Bound::Never) => other,
+                (Bound::Rows(a), Bound::Rows(b)) => {
+                    Bound::Rows(Predicate::Or(Box::new(a), Box::new(b)))
+                }
+            },
+            _ if self
+                .columns()
+                .iter()
+                .all(|c| known.column_by_name(c).is_some()) =>
+            {
+                // Negations sit in the comparisons, so null can be taken as no match
+                let result = self.evaluate(known)?;
+                if result.is_valid(0) && result.value(0) {
+                    Bound::Always
+                } else {
+                    Bound::Never
+                }
+            }
+            _ => Bound::Rows(self.clone()),
+        })
+    }
+
+    // For every row group of the file, whether its statistics allow it to contain a match
+    pub fn prune_row_groups(
+        &self,
+        metadata: &ParquetMetaData,
+        arrow_schema: &Schema,
+    ) -> Result<Vec<bool>, Box<dyn Error>> {
+        let row_groups = metadata.row_groups();
+        let schema_descr = metadata.file_metadata().schema_descr();
+        let row_counts =
+            UInt64Array::from_iter_values(row_groups.iter().map(|rg| rg.num_rows() as u64));
+        let mut stats = |column: &str| -> Result<Option<ContainerStats>, Box<dyn Error>> {
+            let Some(converter) = statistics_converter(column, arrow_schema, schema_descr)? else {
+                return Ok(None);
+            };
+            Ok(Some(ContainerStats {
+                mins: converter.row_group_mins(row_groups.iter())?,
+                maxes: converter.row_group_maxes(row_groups.iter())?,
+                null_counts: converter.row_group_null_counts(row_groups.iter())?,
+                row_counts: row_counts.clone(),
+            }))
+        };
+        let may_match = self.may_match(&mut stats, row_groups.len())?;
+        Ok(to_keep(&may_match))
+    }
+
+    // Rows of the given row groups left after skipping pages whose column index proves
+    // they cannot match. None when the file has no page index or nothing can be skipped.
+    pub fn page_selection(
+        &self,
+        metadata: &ParquetMetaData,
+        arrow_schema: &Schema,
+        row_groups: &[usize],
+    ) -> Result<Option<RowSelection>, Box<dyn Error>> {
+        let (Some(column_index), Some(offset_index)) =
+            (metadata.column_index(), metadata.offset_index())
+        else {
+            return Ok(None);
+        };
+        if row_groups.is_empty() {
+            return Ok(None);
+        }
+        let schema_descr = metadata.file_metadata().schema_descr();
+        match self {
+            Predicate::And(a, b) => {
+                let a = a.page_selection(metadata, arrow_schema, row_groups)?;
+                let b = b.page_selection(metadata, arrow_schema, row_groups)?;
+                Ok(match (a, b) {
+                    (Some(a), Some(b)) => Some(a.intersection(&b)),
+                    (a, b) => a.or(b),
+                })
+            }
+            Predicate::Or(a, b) => {
+                let a = a.page_selection(metadata, arrow_schema, row_groups)?;
+                let b = b.page_selection(metadata, arrow_schema, row_groups)?;
+                Ok(match (a, b) {
+                    (Some(a), Some(b)) => Some(a.union(&b)),
+                    _ => None,
+                })
+            }
+            Predicate::Compare { column, .. }
+            | Predicate::IsNull { column, .. }
+            | Predicate::InList { column, .. }
+            | Predicate::Like { column, .. } => {
+                let Some(converter) = statistics_converter(column, arrow_schema, schema_descr)?
+                else {
+                    return Ok(None);
+                };
+                let Some(row_counts) = converter.data_page_row_counts(
+                    offset_index,
+                    metadata.row_groups(),
+                    row_groups,
+                )?
+                else {
+                    return Ok(None);
+                };
+                let stats = ContainerStats {
+                    mins: converter.data_page_mins(column_index, offset_index, row_groups)?,
+                    maxes: converter.data_page_maxes(column_index, offset_index, row_groups)?,
+                    null_counts: converter.data_page_null_counts(
+                        column_index,
+                        offset_index,
+                        row_groups,
+                    )?,
+                    row_counts,
+                };
+                let pages = stats.row_counts.len();
+                let row_counts = stats.row_counts.clone();
+                let mut single = |_: &str| Ok(Some(stats.clone()));
+                let may_match = self.may_match(&mut single, pages)?;
+                let selectors: Vec<RowSelector> = to_keep(&may_match)
+                    .into_iter()
+                    .zip(row_counts.iter())
+                    .map(|(keep, rows)| {
+                        let rows = rows.unwrap_or_default() as usize;
+                        if keep {
+                            RowSelector::select(rows)
+                        } else {
+                            RowSelector::skip(rows)
+                        }
+                    })
+                    .collect();
+                Ok(Some(RowSelection::from(selectors)))
+            }
+        }
+    }
+
+    // Whether each container (row group or page) described by `stats` may hold a
+    // matching row; null where the statistics can't tell
+    fn may_match(
+        &self,
+        stats: &mut StatsLookup,
+        len: usize,
+    ) -> Result<BooleanArray, Box<dyn Error>> {
+        let unknown = || BooleanArray::new_null(len);
+        Ok(match self {
+            Predicate::And(a, b) => {
+                and_kleene(&a.may_match(stats, len)?, &b.may_match(stats, len)?)?
+            }
+            Predicate::Or(a, b) => or_kleene(&a.may_match(stats, len)?, &b.may_match(stats, len)?)?,
+            Predicate::Compare { column, op, value } => match stats(column)? {
+                Some(stats) => stats.compare(*op, value)?,
+                None => unknown(),
+            },
+            Predicate::IsNull { column, negated } => match stats(column)? {
+                Some(stats) if *negated => lt(&stats.null_counts, &stats.row_counts)?,
+                Some(stats) => gt(&stats.null_counts, &Scalar::new(UInt64Array::from(vec![0])))?,
+                None => unknown(),
+            },
+            Predicate::InList {
+                column,
+                values,
+                negated,
+            } => match stats(column)? {
+                Some(stats) => {
+                    let op = if *negated {
+                        CompareOp::NotEq
+                    } else {
+                        CompareOp::Eq
+                    };
+                    let mut result: Option<BooleanArray> = None;
+                    for value in values {
+                        let matches = stats.compare(op, value)?;
+                        result = Some(match result {
+                            None => matches,
+                            Some(r) if *negated => and_kleene(&r, &matches)?,
+                            Some(r) => or_kleene(&r, &matches)?,
+                        });
+                    }
+                    result.unwrap_or_else(unknown)
+                }
+                None => unknown(),
+            },
+            Predicate::Like { .. } => unknown(),
+        })
+    }
+}
+
+// Looks up the statistics of a column, None when the file doesn't have any
+type StatsLookup<'a> = dyn FnMut(&str) -> Result<Option<ContainerStats>, Box<dyn Error>> + 'a;
+
+// Min/max statistics of one column over a set of row groups or pages
+#[derive(Clone)]
+struct ContainerStats {
+    mins: ArrayRef,
+    maxes: ArrayRef,
+    null_counts: UInt64Array,
+    row_counts: UInt64Array,
+}
+
+impl ContainerStats {
+    fn compare(&self, op: CompareOp, value: &Literal) -> Result<BooleanArray, ArrowError> {
+        Ok(match op {
+            CompareOp::Eq => and_kleene(
+                &compare(&self.mins, CompareOp::LtEq, value)?,
+                &compare(&self.maxes, CompareOp::GtEq, value)?,
+            )?,
+            // only a container holding nothing but the value can be ruled out
+            CompareOp::NotEq => not(&and_kleene(
+                &compare(&self.mins, CompareOp::Eq, value)?,
+                &compare(&self.maxes, CompareOp::Eq, value)?,
+            )?)?,
+            CompareOp::Lt | CompareOp::LtEq => compare(&self.mins, op, value)?,
+            CompareOp::Gt | CompareOp::GtEq => compare(&self.maxes, op, value)?,
+        })
+    }
+}
+
+fn to_keep(may_match: &BooleanArray) -> Vec<bool> {
+    (0..may_match.len())
+        .map(|i| may_match.is_null(i) || may_match.value(i))
+        .collect()
+}
+
+// Statistics are only available for top level primitive columns
+pub(crate) fn statistics_converter<'a>(
+    column: &str,
+    arrow_schema: &'a Schema,
+    schema_descr: &'a parquet::schema::types::SchemaDescriptor,
+) -> Result<Option<StatisticsConverter<'a>>, Box<dyn Error>> {
+    match arrow_schema.field_with_name(column) {
+        Ok(field) if !field.data_type().is_nested() => Ok(Some(StatisticsConverter::try_new(
+            column,
+            arrow_schema,
+            schema_descr,
+        )?)),
+        _ => Ok(None),
+    }
+}
+
+// Compare every value of `array` with a literal, converting the literal to the column type.
+// Strings are ordered as text, so ordering them against a number is refused rather than
+// comparing `10` before `9`.
+pub(crate) fn compare(
+    array: &ArrayRef,
+    op: CompareOp,
+    value: &Literal,
+) -> Result<BooleanArray, ArrowError> {
+    let array = match array.data_type() {
+        DataType::Dictionary(_, value_type) => cast(array, value_type)?,
+        // a column of nulls, e.g. one a file lacks, compares as unknown to anything
+        DataType::Null => return Ok(BooleanArray::new_null(array.len())),
+        _ => array.clone(),
+    };
+    let number = match value {
+        Literal::Int(v) => Some(v.to_string()),
+        Literal::Float(v) => Some(v.to_string()),
+        _ => None,
+    };
+    if let Some(number) = number
+        && matches!(
+            array.data_type(),
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+        )
+        && !matches!(op, CompareOp::Eq | CompareOp::NotEq)
+    {
+        return Err(ArrowError::InvalidArgumentError(format!(
+            "Can't order strings against the number {}, write '{}' to compare as text",
+            number, number
+        )));
+    }
+    let (left, right) = if matches!(value, Literal::Float(_)) && array.data_type().is_integer() {
+        // don't truncate 2.5 to 2, compare as floats instead
+        (cast(&array, &DataType::Float64)?, value.to_array())
+    } else {
+        let options = CastOptions {
+            safe: false,
+            ..Default::default()
+        };
+        match cast_with_options(&value.to_array(), array.data_type(), &options) {
+            Ok(right) => (array, right),
+            // a number out of the column type's range, e.g. 3000000000 for an Int32
+            // column, is compared in a type both fit in
+            Err(e) => match wider_type(array.data_type(), value) {
+                Some(wider) => (
+                    cast(&array, &wider)?,
+                    cast_with_options(&value.to_array(), &wider, &options)?,
+                ),
+                None => return Err(e),
+            },
+        }
+    };
+    let right = Scalar::new(right);
+    let left: &dyn Datum = &left;
+    match op {
+        CompareOp::Eq => eq(left, &right),
+        CompareOp::NotEq => neq(left, &right),
+        CompareOp::Lt => lt(left, &right),
+        CompareOp::LtEq => lt_eq(left, &right),
+        CompareOp::Gt => gt(left, &right),
+        CompareOp::GtEq => gt_eq(left, &right),
+    }
+}
+
+// A type holding both every value of the column type and the number
+fn wider_type(data_type: &DataType, value: &Literal) -> Option<DataType> {
+    match (data_type, value) {
+        (data_type, Literal::Int(_)) if data_type.is_integer() => Some(DataType::Decimal128(20, 0)),
+        (
+            DataType::Decimal32(_, scale)
+            | DataType::Decimal64(_, scale)
+            | DataType::Decimal128(_, scale)
+            | DataType::Decimal256(_, scale),
+            Literal::Int(_) | Literal::Float(_),
+        ) => Some(DataType::Decimal256(76, *scale)),
+        _ => None,
+    }
+}
+
+// Find a column by name, `a.b` also looks up field b of struct column a
+pub(crate) fn column_by_path(batch: &RecordBatch, path: &str) -> Result<ArrayRef, ArrowError> {
+    if let Some(column) = batch.column_by_name(path) {
+        return Ok(column.clone());
+    }
+    let mut parts = path.split('.');
+    let mut column = parts
+        .next()
+        .and_then(|name| batch.column_by_name(name))
+        .cloned();
+    for part in parts {
+        column = column.and_then(|c| {
+            c.as_any()
+                .downcast_ref::<StructArray>()
+                .and_then(|s| s.column_by_name(part))
+                .cloned()
+        });
+    }
+    column.ok_or_else(|| ArrowError::SchemaError(format!("No such column: {}", path)))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Token {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Op(String),
+    LParen,
+    RParen,
+    Comma,
+    Star,
+}
+
+pub(crate) fn tokenize(input: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else if c == '*' {
+            tokens.push(Token::Star);
+            i += 1;
+        } else if c == '\'' || c == '"' || c == '`' {
+            // 'string literal', "quoted column" or `quoted column`, quotes escaped by doubling
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("Unterminated quote in filter".into()),
+                    Some(q) if *q == c => {
+                        if chars.get(i + 1) == Some(&c) {
+                            value.push(c);
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    Some(ch) => {
+                        value.push(*ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(if c == '\'' {
+                Token::Str(value)
+            } else {
+                Token::Ident(value)
+            });
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit()))
+        {
+            let start = i;
+            i += 1;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric()
+                    || chars[i] == '.'
+                    || ((chars[i] == '-' || chars[i] == '+') && matches!(chars[i - 1], 'e' | 'E')))
+            {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "=!<>".contains(c) {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let op = match two.as_str() {
+                "==" | "!=" | "<>" | "<=" | ">=" => two,
+                _ if c == '!' => return Err("Unexpected ! in filter".into()),
+                _ => c.to_string(),
+            };
+            i += op.len();
+            tokens.push(Token::Op(op));
+        } else {
+            return Err(format!("Unexpected character {} in filter", c).into());
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Box<dyn Error>> {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("Expected {} in filter", keyword).into())
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Predicate, Box<dyn Error>> {
+        let mut left = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Predicate::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Predicate, Box<dyn Error>> {
+        let mut left = self.parse_not()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            let right = self.parse_not()?;
+            left = Predicate::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Predicate, Box<dyn Error>> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(self.parse_not()?.negate());
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let inner = self.parse_or()?;
+            if self.next() != Some(Token::RParen) {
+                return Err("Missing ) in filter".into());
+            }
+            return Ok(inner);
+        }
+        self.parse_condition()
+    }
+
+    fn parse_condition(&mut self) -> Result<Predicate, Box<dyn Error>> {
+        // `literal op column` is accepted as well and turned around
+        if let Some(Token::Str(_) | Token::Number(_)) = self.peek() {
+            let value = self.parse_literal()?;
+            let op = self.parse_op()?;
+            let column = self.parse_column()?;
+            return Ok(Predicate::Compare {
+                column,
+                op: op.flip(),
+                value,
+            });
+        }
+
+        let column = self.parse_column()?;
+        if self.peek_keyword("is") {
+            self.pos += 1;
+            let negated = self.peek_keyword("not");
+            if negated {
+                self.pos += 1;
+            }
+            self.expect_keyword("null")?;
+            return Ok(Predicate::IsNull { column, negated });
+        }
+        let negated = self.peek_keyword("not");
+        if negated {
+            self.pos += 1;
+        }
+        if self.peek_keyword("in") {
+            self.pos += 1;
+            if self.next() != Some(Token::LParen) {
+                return Err("Expected ( after in".into());
+            }
+            let mut values = vec![self.parse_literal()?];
+            loop {
+                match self.next() {
+                    Some(Token::Comma) => values.push(self.parse_literal()?),
+                    Some(Token::RParen) => break,
+                    _ => return Err("Expected , or ) in list".into()),
+                }
+            }
+            return Ok(Predicate::InList {
+                column,
+                values,
+                negated,
+            });
+        }
+        if self.peek_keyword("like") {
+            self.pos += 1;
+            let Some(Token::Str(pattern)) = self.next() else {
+                return Err("Expected a quoted pattern after like".into());
+            };
+            return Ok(Predicate::Like {
+                column,
+                pattern,
+                negated,
+            });
+        }
+        if negated {
+            return Err("Expected in or like after not".into());
+        }
+        let op = self.parse_op()?;
+        let value = self.parse_literal()?;
+        Ok(Predicate::Compare { column, op, value })
+    }
+
+    fn parse_column(&mut self) -> Result<String, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            Some(token) => Err(format!("Expected a column name, got {:?}", token).into()),
+            None => Err("Expected a column name at the end of filter".into()),
+        }
+    }
+
+    fn parse_op(&mut self) -> Result<CompareOp, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Op(op)) => Ok(match op.as_str() {
+                "=" | "==" => CompareOp::Eq,
+                "!=" | "<>" => CompareOp::NotEq,
+                "<" => CompareOp::Lt,
+                "<=" => CompareOp::LtEq,
+                ">" => CompareOp::Gt,
+                _ => CompareOp::GtEq,
+            }),
+            Some(token) => Err(format!("Expected a comparison, got {:?}", token).into()),
+            None => Err("Expected a comparison at the end of filter".into()),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Str(value)) => Ok(Literal::Str(value)),
+            Some(Token::Number(value)) => {
+                if let Ok(v) = value.parse::<i64>() {
+                    Ok(Literal::Int(v))
+                } else {
+                    Ok(Literal::Float(
+                        value
+                            .parse::<f64>()
+                            .map_err(|_| format!("Invalid number {}", value))?,
+                    ))
+                }
+            }
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("true") => {
+                Ok(Literal::Bool(true))
+            }
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("false") => {
+                Ok(Literal::Bool(false))
+            }
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("null") => {
+                Err("Use is null / is not null to compare with null".into())
+            }
+            Some(token) => Err(format!("Expected a value, got {:?}", token).into()),
+            None => Err("Expected a value at the end of filter".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::UInt8Array;
+    use arrow::datatypes::Field;
+
+    fn col(name: &str, op: CompareOp, value: Literal) -> Predicate {
+        Predicate::Compare {
+            column: name.to_string(),
+            op,
+            value,
+        }
+    }
+
+    fn batch() -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("country", DataType::Utf8, true),
+            Field::new("amount", DataType::Float64, true),
+            Field::new("level", DataType::UInt8, false),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4])),
+                Arc::new(StringArray::from(vec![
+                    Some("CZ"),
+                    Some("DE"),
+                    None,
+                    Some("it's"),
+                ])),
+                Arc::new(Float64Array::from(vec![
+                    Some(1.5),
+                    None,
+                    Some(10.0),
+                    Some(2.5),
+                ])),
+                Arc::new(UInt8Array::from(vec![1, 2, 3, 4])),
+            ],
+        )
+        .unwrap()
+    }
+
+    // The ids of the rows matching the filter
+    fn matching(filter: &str) -> Vec<i64> {
+        let batch = batch();
+        let result = Predicate::parse(filter).unwrap().evaluate(&batch).unwrap();
+        (0..batch.num_rows())
+            .filter(|i| result.is_valid(*i) && result.value(*i))
+            .map(|i| i as i64 + 1)
+            .collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let a = col("a", CompareOp::Eq, Literal::Int(1));
+        let b = col("b", CompareOp::Eq, Literal::Int(2));
+        let c = col("c", CompareOp::Eq, Literal::Int(3));
+        assert_eq!(
+            Predicate::parse("a = 1 or b = 2 and c = 3").unwrap(),
+            Predicate::Or(
+                Box::new(a.clone()),
+                Box::new(Predicate::And(Box::new(b.clone()), Box::new(c.clone())))
+            )
+        );
+        assert_eq!(
+            Predicate::parse("(a = 1 or b = 2) and c = 3").unwrap(),
+            Predicate::And(
+                Box::new(Predicate::Or(Box::new(a), Box::new(b))),
+                Box::new(c)
+            )
+        );
+    }
+
+    #[test]
+    fn not_is_pushed_into_comparisons() {
+        assert_eq!(
+            Predicate::parse("not (a < 1 or b is null)").unwrap(),
+            Predicate::And(
+                Box::new(col("a", CompareOp::GtEq, Literal::Int(1))),
+                Box::new(Predicate::IsNull {
+                    column: "b".to_string(),
+                    negated: true
+                })
+            )
+        );
+        assert_eq!(
+            Predicate::parse("5 > a").unwrap(),
+            col("a", CompareOp::Lt, Literal::Int(5))
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            Predicate::parse("\"my col\" = 'it''s'").unwrap(),
+            col("my col", CompareOp::Eq, Literal::Str("it's".to_string()))
+        );
+        assert_eq!(
+            Predicate::parse("`a.b` <> -2.5e3").unwrap(),
+            col("a.b", CompareOp::NotEq, Literal::Float(-2500.0))
+        );
+        assert!(Predicate::parse("a = 'open").is_err());
+        assert!(Predicate::parse("a = null").is_err());
+        assert!(Predicate::parse("a = 1 b").is_err());
+    }
+
+    #[test]
+    fn evaluate_conditions() {
+        assert_eq!(matching("country = 'CZ' or amount > 2"), vec![1, 3, 4]);
+        assert_eq!(matching("country in ('DE', 'it''s')"), vec![2, 4]);
+        // null is neither in nor out of the list
+        assert_eq!(matching("country not in ('DE')"), vec![1, 4]);
+        assert_eq!(matching("country like '%''%'"), vec![4]);
+        assert_eq!(matching("country not like 'C_'"), vec![2, 4]);
+        assert_eq!(matching("amount is null"), vec![2]);
+        assert_eq!(matching("amount is not null and id != 4"), vec![1, 3]);
+        assert_eq!(matching("amount = 10"), vec![3]);
+        assert_eq!(matching("id < 2.5"), vec![1, 2]);
+    }
+
+    #[test]
+    fn strings_are_not_ordered_against_numbers() {
+        let batch = batch();
+        assert!(
+            Predicate::parse("country > 9")
+                .unwrap()
+                .evaluate(&batch)
+                .is_err()
+        );
+        assert!(
+            Predicate::parse("country = 9")
+                .unwrap()
+                .evaluate(&batch)
+                .is_ok()
+        );
+        assert!(
+            Predicate::parse("country > '9'")
+                .unwrap()
+                .evaluate(&batch)
+                .is_ok()
+        );
+    }
+
+    // Three containers of `id`: 1..=10, 11..=20 and one of only nulls
+    fn stats() -> ContainerStats {
+        ContainerStats {
+            mins: Arc::new(Int64Array::from(vec![Some(1), Some(11), None])),
+            maxes: Arc::new(Int64Array::from(vec![Some(10), Some(20), None])),
+            null_counts: UInt64Array::from(vec![0, 2, 5]),
+            row_counts: UInt64Array::from(vec![10, 10, 5]),
+        }
+    }
+
+    fn kept(filter: &str) -> Vec<bool> {
+        let stats = stats();
+        let mut lookup = |column: &str| {
+            Ok(match column {
+                "id" => Some(stats.clone()),
+                _ => None,
+            })
+        };
+        let may_match = Predicate::parse(filter)
+            .unwrap()
+            .may_match(&mut lookup, 3)
+            .unwrap();
+        to_keep(&may_match)
+    }
+
+    #[test]
+    fn prune_by_statistics() {
+        assert_eq!(kept("id = 15"), vec![false, true, true]);
+        assert_eq!(kept("id > 10"), vec![false, true, true]);
+        assert_eq!(kept("id <= 10"), vec![true, false, true]);
+        assert_eq!(kept("id in (3, 30)"), vec![true, false, true]);
+        assert_eq!(kept("id is null"), vec![false, true, true]);
+        assert_eq!(kept("id is not null"), vec![true, true, false]);
+        // columns without statistics can't rule anything out
+        assert_eq!(kept("id = 15 or other = 1"), vec![true, true, true]);
+        assert_eq!(kept("id = 15 and other = 1"), vec![false, true, true]);
+        assert_eq!(kept("id like '1%'"), vec![true, true, true]);
+    }
+
+    #[test]
+    fn not_equal_prunes_only_single_value_containers() {
+        let stats = ContainerStats {
+            mins: Arc::new(Int64Array::from(vec![7, 7])),
+            maxes: Arc::new(Int64Array::from(vec![7, 8])),
+            null_counts: UInt64Array::from(vec![0, 0]),
+            row_counts: UInt64Array::from(vec![3, 3]),
+        };
+        let mut lookup = |_: &str| Ok(Some(stats.clone()));
+        let may_match = Predicate::parse("id != 7")
+            .unwrap()
+            .may_match(&mut lookup, 2)
+            .unwrap();
+        assert_eq!(to_keep(&may_match), vec![false, true]);
+    }
+
+    #[test]
+    fn numbers_out_of_the_column_range() {
+        assert_eq!(matching("level = 300"), Vec::<i64>::new());
+        assert_eq!(matching("level != 300"), vec![1, 2, 3, 4]);
+        assert_eq!(matching("level < 300"), vec![1, 2, 3, 4]);
+        assert_eq!(matching("level >= 300"), Vec::<i64>::new());
+        assert_eq!(matching("level > -1"), vec![1, 2, 3, 4]);
+        assert_eq!(matching("level in (-1, 2, 300)"), vec![2]);
+    }
+}
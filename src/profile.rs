@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use arrow::array::{Array, ArrayRef, AsArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::DataType;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use futures::TryStreamExt;
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use parquet::arrow::async_reader::AsyncFileReader;
+
+use crate::pqt::projection_mask;
+use crate::utils::{SplitMix64, compute_col_max_len, print_rows, print_rows_left};
+
+// 2^14 HyperLogLog registers, the distinct estimates are within about 1% of the truth
+const HLL_PRECISION: u32 = 14;
+
+// Values the top-k counters track at once; more distinct values make the counts lower bounds
+const TOP_VALUES_CAPACITY: usize = 1024;
+
+// Most frequent values shown per column
+const TOP_VALUES_SHOWN: usize = 5;
+
+// Numbers kept per column to estimate the quantiles from
+const QUANTILE_SAMPLE_SIZE: usize = 10_000;
+
+const QUANTILES: [f64; 5] = [0.05, 0.25, 0.5, 0.75, 0.95];
+
+// Min/max strings are cut to this many characters
+const MAX_VALUE_LEN: usize = 40;
+
+// Stream the selected columns, or all of them, through per column profiles that each
+// take a fixed amount of memory, stopping after `sample` rows when given
+pub async fn parquet_profile<T: AsyncFileReader + Unpin + Send + 'static>(
+    reader: T,
+    columns: &[String],
+    sample: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let mut builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
+    let num_rows = builder.metadata().file_metadata().num_rows();
+    if !columns.is_empty() {
+        let mask = projection_mask(builder.parquet_schema(), columns)?;
+        builder = builder.with_projection(mask);
+    }
+    if let Some(sample) = sample {
+        builder = builder.with_limit(sample);
+    }
+    let mut stream = builder.with_batch_size(8192).build()?;
+
+    let mut profiles: Vec<ColumnProfile> = vec![];
+    let mut rows = 0;
+    while let Some(batch) = stream.try_next().await? {
+        let leaves = leaf_columns(&batch);
+        if profiles.is_empty() {
+            profiles = leaves
+                .iter()
+                .map(|(name, array)| ColumnProfile::new(name, array.data_type()))
+                .collect();
+        }
+        for (profile, (_, array)) in profiles.iter_mut().zip(leaves.iter()) {
+            profile.add(array)?;
+        }
+        rows += batch.num_rows();
+    }
+    println!("Profiled {} of {} rows", rows, num_rows);
+    if profiles.is_empty() {
+        return Ok(());
+    }
+
+    print_overview(&profiles);
+    print_quantiles(&profiles);
+    print_lengths(&profiles);
+    for profile in profiles.iter() {
+        profile.print_top_values();
+    }
+    Ok(())
+}
+
+// Columns of a batch with structs split into their fields, named by dotted paths
+fn leaf_columns(batch: &RecordBatch) -> Vec<(String, ArrayRef)> {
+    fn push(name: String, array: &ArrayRef, leaves: &mut Vec<(String, ArrayRef)>) {
+        match array.as_struct_opt() {
+            Some(array) => {
+                for (field, child) in array.fields().iter().zip(array.columns()) {
+                    push(format!("{}.{}", name, field.name()), child, leaves);
+                }
+            }
+            None => leaves.push((name, array.clone())),
+        }
+    }
+    let mut leaves = vec![];
+    for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+        push(field.name().clone(), array, &mut leaves);
+    }
+    leaves
+}
+
+struct ColumnProfile {
+    name: String,
+    data_type: DataType,
+    rows: usize,
+    nulls: usize,
+    distinct: HyperLogLog,
+    top_values: TopValues,
+    numbers: Option<NumberStats>,
+    strings: Option<StringStats>,
+}
+
+impl ColumnProfile {
+    fn new(name: &str, data_type: &DataType) -> Self {
+        // dictionary encoded columns are profiled by the type of their values
+        let value_type = match data_type {
+            DataType::Dictionary(_, value_type) => value_type.as_ref(),
+            data_type => data_type,
+        };
+        let is_string = matches!(
+            value_type,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+        );
+        Self {
+            name: name.to_string(),
+            data_type: data_type.clone(),
+            rows: 0,
+            nulls: 0,
+            distinct: HyperLogLog::new(),
+            top_values: TopValues::new(),
+            numbers: value_type.is_numeric().then(NumberStats::new),
+            strings: is_string.then(StringStats::new),
+        }
+    }
+
+    fn add(&mut self, array: &ArrayRef) -> Result<(), Box<dyn Error>> {
+        self.rows += array.len();
+        self.nulls += array.logical_null_count();
+
+        let formatter = ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default())?;
+        for i in 0..array.len() {
+            if array.is_null(i) {
+                continue;
+            }
+            let value = formatter.value(i).to_string();
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            self.distinct.add(hasher.finish());
+            if let Some(strings) = &mut self.strings {
+                strings.add(&value);
+            }
+            self.top_values.add(value);
+        }
+
+        if let Some(numbers) = &mut self.numbers {
+            let floats = cast(array, &DataType::Float64)?;
+            for value in floats
+                .as_primitive::<arrow::datatypes::Float64Type>()
+                .iter()
+            {
+                if let Some(value) = value
+                    && !value.is_nan()
+                {
+                    numbers.add(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn print_top_values(&self) {
+        let present = self.rows - self.nulls;
+        if present == 0 {
+            return;
+        }
+        let top = self.top_values.top(TOP_VALUES_SHOWN);
+        if top.first().is_none_or(|(_, count)| *count < 2) {
+            println!("Top values of {}: no value stands out", self.name);
+            return;
+        }
+        let approximate = if self.top_values.exact { "" } else { "~" };
+        let mut rows = vec![vec![
+            "Value".to_string(),
+            "Count".to_string(),
+            "Share".to_string(),
+        ]];
+        for (value, count) in top {
+            rows.push(vec![
+                truncate(&value),
+                format!("{}{}", approximate, count),
+                format!(
+                    "{}{:.1}%",
+                    approximate,
+                    100.0 * count as f64 / present as f64
+                ),
+            ]);
+        }
+        println!("Top values of {}:", self.name);
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows_left(&rows, &col_max_len, true);
+    }
+}
+
+fn print_overview(profiles: &[ColumnProfile]) {
+    let mut rows = vec![
+        [
+            "Column", "Type", "Nulls", "Distinct", "Min", "Max", "Mean", "Stddev",
+        ]
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<String>>(),
+    ];
+    for p in profiles {
+        let nulls = if p.rows == 0 {
+            String::new()
+        } else {
+            format!("{:.1}%", 100.0 * p.nulls as f64 / p.rows as f64)
+        };
+        let (min, max, mean, stddev) = match (&p.numbers, &p.strings) {
+            (Some(n), _) if n.count > 0 => (
+                number(n.min),
+                number(n.max),
+                number(n.mean),
+                number(n.stddev()),
+            ),
+            (_, Some(s)) => (
+                s.min.as_deref().map(truncate).unwrap_or_default(),
+                s.max.as_deref().map(truncate).unwrap_or_default(),
+                String::new(),
+                String::new(),
+            ),
+            _ => Default::default(),
+        };
+        rows.push(vec![
+            p.name.clone(),
+            type_name(&p.data_type),
+            nulls,
+            format!("~{}", p.distinct.estimate()),
+            min,
+            max,
+            mean,
+            stddev,
+        ]);
+    }
+    let col_max_len = compute_col_max_len(&rows);
+    print_rows(&rows, &col_max_len, true);
+}
+
+fn print_quantiles(profiles: &[ColumnProfile]) {
+    let mut rows = vec![vec!["Column".to_string()]];
+    rows[0].extend(QUANTILES.iter().map(|q| format!("p{}", q * 100.0)));
+    for p in profiles {
+        if let Some(numbers) = &p.numbers
+            && numbers.count > 0
+        {
+            let mut row = vec![p.name.clone()];
+            row.extend(numbers.quantiles().into_iter().map(number));
+            rows.push(row);
+        }
+    }
+    if rows.len() > 1 {
+        println!("Quantiles:");
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows(&rows, &col_max_len, true);
+    }
+}
+
+fn print_lengths(profiles: &[ColumnProfile]) {
+    let mut rows = vec![vec![
+        "Column".to_string(),
+        "Min".to_string(),
+        "Mean".to_string(),
+        "Max".to_string(),
+    ]];
+    rows[0].extend(StringStats::bucket_labels());
+    for p in profiles {
+        if let Some(strings) = &p.strings
+            && strings.count > 0
+        {
+            let mut row = vec![
+                p.name.clone(),
+                strings.min_len.to_string(),
+                format!("{:.1}", strings.total_len as f64 / strings.count as f64),
+                strings.max_len.to_string(),
+            ];
+            row.extend(strings.buckets.iter().map(|c| c.to_string()));
+            rows.push(row);
+        }
+    }
+    if rows.len() > 1 {
+        println!("String lengths:");
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows(&rows, &col_max_len, true);
+    }
+}
+
+// Arrow type without the field details that make nested types unreadably long
+fn type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::List(f) | DataType::LargeList(f) | DataType::FixedSizeList(f, _) => {
+            format!("List({})", type_name(f.data_type()))
+        }
+        DataType::Map(entries, _) => match entries.data_type() {
+            DataType::Struct(fields) if fields.len() == 2 => format!(
+                "Map({}, {})",
+                type_name(fields[0].data_type()),
+                type_name(fields[1].data_type())
+            ),
+            _ => "Map".to_string(),
+        },
+        DataType::Struct(_) => "Struct".to_string(),
+        _ => data_type.to_string(),
+    }
+}
+
+// Whole numbers without a fraction, others rounded
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value)
+    } else {
+        format!("{:.4}", value)
+    }
+}
+
+fn truncate(value: &str) -> String {
+    if value.chars().count() > MAX_VALUE_LEN {
+        let cut: String = value.chars().take(MAX_VALUE_LEN - 3).collect();
+        format!("{}...", cut)
+    } else {
+        value.to_string()
+    }
+}
+
+// Distinct count estimate in a fixed number of registers
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+
+    fn add(&mut self, hash: u64) {
+        let idx = (hash >> (64 - HLL_PRECISION)) as usize;
+        // The marker bit caps the rank when the remaining bits are all zero
+        let rest = (hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if self.registers[idx] < rank {
+            self.registers[idx] = rank;
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        // Linear counting is more accurate while many registers are still empty
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+// Misra-Gries frequent values: when the counters are full they are all decremented and
+// the ones reaching zero dropped, so frequent values survive with a lower bound count
+struct TopValues {
+    counts: HashMap<String, u64>,
+    // no counter was ever decremented, the counts are exact
+    exact: bool,
+}
+
+impl TopValues {
+    fn new() -> Self {
+        Self {
+            counts: HashMap::new(),
+            exact: true,
+        }
+    }
+
+    fn add(&mut self, value: String) {
+        if let Some(count) = self.counts.get_mut(&value) {
+            *count += 1;
+            return;
+        }
+        if self.counts.len() < TOP_VALUES_CAPACITY {
+            self.counts.insert(value, 1);
+            return;
+        }
+        self.exact = false;
+        self.counts.retain(|_, count| {
+            *count -= 1;
+            *count > 0
+        });
+    }
+
+    fn top(&self, k: usize) -> Vec<(String, u64)> {
+        let mut top: Vec<(String, u64)> = self
+            .counts
+            .iter()
+            .map(|(value, count)| (value.clone(), *count))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(k);
+        top
+    }
+}
+
+// Running moments (Welford) and a reservoir sample for the quantiles
+struct NumberStats {
+    count: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+    sample: Vec<f64>,
+    rng: SplitMix64,
+}
+
+impl NumberStats {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            sample: vec![],
+            rng: SplitMix64::new(0),
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+
+        if self.sample.len() < QUANTILE_SAMPLE_SIZE {
+            self.sample.push(value);
+        } else {
+            let slot = self.rng.below(self.count) as usize;
+            if slot < QUANTILE_SAMPLE_SIZE {
+                self.sample[slot] = value;
+            }
+        }
+    }
+
+    fn stddev(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        (self.m2 / (self.count - 1) as f64).sqrt()
+    }
+
+    fn quantiles(&self) -> Vec<f64> {
+        let mut sorted = self.sample.clone();
+        sorted.sort_by(f64::total_cmp);
+        QUANTILES
+            .iter()
+            .map(|q| sorted[((sorted.len() - 1) as f64 * q).round() as usize])
+            .collect()
+    }
+}
+
+// Lengths in characters, bucketed by powers of two
+struct StringStats {
+    count: u64,
+    total_len: u64,
+    min_len: usize,
+    max_len: usize,
+    min: Option<String>,
+    max: Option<String>,
+    buckets: [u64; 8],
+}
+
+impl StringStats {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            total_len: 0,
+            min_len: usize::MAX,
+            max_len: 0,
+            min: None,
+            max: None,
+            buckets: [0; 8],
+        }
+    }
+
+    fn bucket_labels() -> Vec<String> {
+        [
+            "0", "1-4", "5-8", "9-16", "17-32", "33-64", "65-128", ">128",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+    }
+
+    fn add(&mut self, value: &str) {
+        let len = value.chars().count();
+        self.count += 1;
+        self.total_len += len as u64;
+        self.min_len = self.min_len.min(len);
+        self.max_len = self.max_len.max(len);
+        let bucket = match len {
+            0 => 0,
+            1..=4 => 1,
+            5..=128 => (len - 1).ilog2() as usize,
+            _ => 7,
+        };
+        self.buckets[bucket] += 1;
+        if self.min.as_deref().is_none_or(|min| value < min) {
+            self.min = Some(value.to_string());
+        }
+        if self.max.as_deref().is_none_or(|max| value > max) {
+            self.max = Some(value.to_string());
+        }
+    }
+}
@@ -3,8 +3,9 @@ use std::fs::File;
 use std::io::Read;
 use std::vec;
 
-use crate::provider::{FileContent, Provider};
+use crate::provider::{FileContent, ParquetSource, Provider};
 
+#[derive(Default)]
 pub struct LocalFs {}
 
 impl LocalFs {
@@ -12,7 +13,7 @@ impl LocalFs {
         Self {}
     }
 
-    fn get_local_file_content(path: &String) -> Result<Vec<u8>, Box<dyn Error>> {
+    fn get_local_file_content(path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
         let mut buf: Vec<u8> = vec![];
         let mut file = File::open(path)?;
         file.read_to_end(&mut buf)?;
@@ -25,51 +26,66 @@ impl Provider for LocalFs {
     // Read the contents of a file
 
     // List files in a directory
-    async fn list_dir(
-        &self,
-        cwd: &String,
-        path: &String,
-    ) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    async fn list_dir(&self, cwd: &str, path: &str) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
         println!("Listing {path} from: {cwd}"); // Debug print
 
         let mut files = vec![];
         let pattern = format!("{cwd}/{path}");
-        for entry in glob::glob(&pattern)? {
-            if let Ok(path) = entry {
-                let file_name = path
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string();
-                files.push(vec![file_name]);
-            }
+        for path in glob::glob(&pattern)?.flatten() {
+            let file_name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            files.push(vec![file_name]);
         }
         return Ok(files);
     }
 
+    async fn find(&self, cwd: &str, pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let cwd = std::path::Path::new(cwd);
+        let mut files = vec![];
+        for path in glob::glob(&cwd.join(pattern).to_string_lossy())?.flatten() {
+            if path.is_file() {
+                let relative = path.strip_prefix(cwd).unwrap_or(&path);
+                files.push(relative.to_string_lossy().to_string());
+            }
+        }
+        Ok(files)
+    }
+
     async fn list_zip(
         &self,
-        zip_file: &String,
-        glob: &String,
+        zip_file: &str,
+        glob: &str,
     ) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
         println!("Listing zip: {zip_file}/{glob}"); // Debug print
 
-        let files = crate::zip::zip_list_from_local_file(&zip_file)?;
+        let files = crate::zip::zip_list_from_local_file(zip_file)?;
         return Ok(files); // Return empty list as zip_list handles printing
     }
 
-    async fn get_file(&self, path: &String) -> Result<Vec<FileContent>, Box<dyn Error>> {
+    async fn get_file(&self, path: &str) -> Result<Vec<FileContent>, Box<dyn Error>> {
         let content = Self::get_local_file_content(path)?;
         Ok(vec![FileContent {
-            filename: path.clone(),
+            filename: path.to_string(),
             content,
         }])
     }
 
+    async fn open_parquet(&self, path: &str) -> Result<ParquetSource, Box<dyn Error>> {
+        let file = tokio::fs::File::open(path).await?;
+        Ok(ParquetSource {
+            filename: path.to_string(),
+            size: Some(file.metadata().await?.len()),
+            reader: Box::new(file),
+        })
+    }
+
     async fn get_file_from_zip(
         &self,
-        path: &String,
-        glob: &String,
+        path: &str,
+        glob: &str,
     ) -> Result<Vec<FileContent>, Box<dyn Error>> {
         let mut file_collection = vec![];
         let files = crate::zip::zip_list_from_local_file(path)?;
@@ -1,8 +1,23 @@
-use reqwest::{Client, header};
+use bytes::Bytes;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use parquet::arrow::arrow_reader::ArrowReaderOptions;
+use parquet::arrow::async_reader::{AsyncFileReader, MetadataSuffixFetch};
+use parquet::errors::ParquetError;
+use parquet::file::metadata::{PageIndexPolicy, ParquetMetaData, ParquetMetaDataReader};
+use reqwest::{Client, StatusCode, header};
 use serde::Deserialize;
 use std::error::Error;
+use std::ops::Range;
+use std::sync::Arc;
 
-use crate::provider::{FileContent, Provider};
+use crate::provider::{FileContent, ParquetSource, Provider};
+
+// How much of the object tail to fetch up front when reading a Parquet footer.
+// Big enough to cover the metadata of most files in a single request.
+const FOOTER_PREFETCH_SIZE: usize = 64 * 1024;
+
+#[derive(Clone)]
 pub struct PublicHttpEndpoint {
     pub(crate) client: Client,
 }
@@ -23,58 +38,191 @@ impl PublicHttpEndpoint {
         Ok(Self { client })
     }
 
-    // Get file (supports byte ranges)
-    async fn get(
-        &self,
-        url: &String,
-        range: Option<(u64, u64)>,
-    ) -> Result<Vec<u8>, Box<dyn Error>> {
+    // Get file (supports byte ranges). A server that ignores the range and sends the
+    // whole object is an error, its bytes aren't the ones asked for.
+    async fn get(&self, url: &str, range: Option<(u64, u64)>) -> Result<Vec<u8>, Box<dyn Error>> {
         let mut request = self.client.get(url);
         if let Some((start, end)) = range {
             let range_header = format!("bytes={}-{}", start, end);
             request = request.header(header::RANGE, range_header);
         }
-        let response = request.send().await?;
+        let response = request.send().await?.error_for_status()?;
+        let partial = response.status() == StatusCode::PARTIAL_CONTENT;
         let bytes = response.bytes().await?;
+        if let Some((start, end)) = range
+            && !partial
+            && bytes.len() as u64 != end - start + 1
+        {
+            return Err(format!(
+                "{} ignored the range bytes={}-{} and sent {} bytes",
+                url,
+                start,
+                end,
+                bytes.len()
+            )
+            .into());
+        }
         Ok(bytes.to_vec())
     }
+
+    // Get the last `len` bytes of a file, or all of it when it's shorter
+    async fn get_suffix(&self, url: &str, len: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let range_header = format!("bytes=-{}", len);
+        let response = self
+            .client
+            .get(url)
+            .header(header::RANGE, range_header)
+            .send()
+            .await?
+            .error_for_status()?;
+        let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+        let bytes = response.bytes().await?;
+        if !partial && bytes.len() > len {
+            return Err(format!(
+                "{} ignored the range bytes=-{} and sent {} bytes",
+                url,
+                len,
+                bytes.len()
+            )
+            .into());
+        }
+        Ok(bytes.to_vec())
+    }
+
+    // Async Parquet reader over a remote object, fetching only the byte ranges it needs
+    pub fn parquet_reader(&self, url: &str) -> HttpParquetReader {
+        HttpParquetReader {
+            endpoint: self.clone(),
+            url: url.to_string(),
+        }
+    }
+}
+
+pub struct HttpParquetReader {
+    endpoint: PublicHttpEndpoint,
+    url: String,
+}
+
+impl HttpParquetReader {
+    async fn fetch_range(&self, range: Range<u64>) -> parquet::errors::Result<Bytes> {
+        if range.is_empty() {
+            return Ok(Bytes::new());
+        }
+        let content = self
+            .endpoint
+            .get(&self.url, Some((range.start, range.end - 1)))
+            .await
+            .map_err(to_parquet_error)?;
+        Ok(Bytes::from(content))
+    }
+}
+
+fn to_parquet_error(e: Box<dyn Error>) -> ParquetError {
+    ParquetError::General(e.to_string())
+}
+
+impl AsyncFileReader for HttpParquetReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, parquet::errors::Result<Bytes>> {
+        self.fetch_range(range).boxed()
+    }
+
+    // Column chunks of a row group are independent ranges, fetch them concurrently
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, parquet::errors::Result<Vec<Bytes>>> {
+        async move {
+            let reader = &*self;
+            let fetches = ranges.into_iter().map(|range| reader.fetch_range(range));
+            futures::future::try_join_all(fetches).await
+        }
+        .boxed()
+    }
+
+    fn get_metadata<'a>(
+        &'a mut self,
+        options: Option<&'a ArrowReaderOptions>,
+    ) -> BoxFuture<'a, parquet::errors::Result<Arc<ParquetMetaData>>> {
+        async move {
+            let decryption = options.and_then(|o| o.file_decryption_properties().cloned());
+            let metadata = ParquetMetaDataReader::new()
+                .with_prefetch_hint(Some(FOOTER_PREFETCH_SIZE))
+                .with_decryption_properties(decryption.clone())
+                .load_via_suffix_and_finish(&mut *self)
+                .await?;
+
+            // The page indexes are loaded separately by their exact ranges, the suffix
+            // reader doesn't know where its prefetched bytes sit in larger files
+            if !options.is_some_and(|o| o.page_index()) {
+                return Ok(Arc::new(metadata));
+            }
+            let mut metadata_reader = ParquetMetaDataReader::new_with_metadata(metadata)
+                .with_page_index_policy(PageIndexPolicy::Optional)
+                .with_decryption_properties(decryption);
+            metadata_reader.load_page_index(&mut *self).await?;
+            Ok(Arc::new(metadata_reader.finish()?))
+        }
+        .boxed()
+    }
+}
+
+impl MetadataSuffixFetch for &mut HttpParquetReader {
+    fn fetch_suffix(&mut self, suffix: usize) -> BoxFuture<'_, parquet::errors::Result<Bytes>> {
+        async move {
+            let content = self
+                .endpoint
+                .get_suffix(&self.url, suffix)
+                .await
+                .map_err(to_parquet_error)?;
+            Ok(Bytes::from(content))
+        }
+        .boxed()
+    }
 }
 #[async_trait::async_trait]
 impl Provider for PublicHttpEndpoint {
     // List contents of a URL
     async fn list_dir(
         &self,
-        base_url: &String,
-        path: &String,
+        base_url: &str,
+        path: &str,
     ) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
-        println!("HTTP: list_dir(): Listing URL: {base_url} with glob {path}"); // Debug print
-
-        let response = self.client.get(base_url).send().await?;
+        let response = self.client.get(base_url).send().await?.error_for_status()?;
         let body = response.text().await?;
-        println!("Response Body: {}", body); // Debug print
-
         let list: ListResponse = serde_json::from_str(&body)?;
         let mut files = vec![];
         let glob = glob::Pattern::new(path)?;
         for entry in list.objects {
-            if let Some(name) = entry.name {
-                if glob.matches(&name) {
-                    files.push(vec![name]);
-                }
+            if let Some(name) = entry.name
+                && glob.matches(&name)
+            {
+                files.push(vec![name]);
             }
         }
         Ok(files)
     }
 
-    async fn list_zip(
-        &self,
-        url: &String,
-        glob: &String,
-    ) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    // Object names are full paths, so `*` stops at a slash as it does on a file system
+    async fn find(&self, base_url: &str, pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let glob = glob::Pattern::new(pattern)?;
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        Ok(self
+            .list_dir(base_url, pattern)
+            .await?
+            .into_iter()
+            .filter_map(|row| row.into_iter().next())
+            .filter(|name| glob.matches_with(name, options))
+            .collect())
+    }
+
+    async fn list_zip(&self, url: &str, glob: &str) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
         let glob = if glob.is_empty() { "*" } else { glob };
         println!("HTTP: list_zip(): Listing URL: {url} with {glob}");
 
-        let files = crate::zip::zip_list_http(&self.client, &url).await?;
+        let files = crate::zip::zip_list_http(&self.client, url).await?;
         let mut filtered = vec![];
         let glob = glob::Pattern::new(glob)?;
         for row in files.iter() {
@@ -86,20 +234,28 @@ impl Provider for PublicHttpEndpoint {
         Ok(filtered)
     }
 
-    async fn get_file(&self, url: &String) -> Result<Vec<FileContent>, Box<dyn Error>> {
+    async fn get_file(&self, url: &str) -> Result<Vec<FileContent>, Box<dyn Error>> {
         let content = self.get(url, None).await?;
         Ok(vec![FileContent {
-            filename: url.clone(),
+            filename: url.to_string(),
             content,
         }])
     }
 
+    async fn open_parquet(&self, url: &str) -> Result<ParquetSource, Box<dyn Error>> {
+        Ok(ParquetSource {
+            filename: url.to_string(),
+            reader: Box::new(self.parquet_reader(url)),
+            size: None,
+        })
+    }
+
     async fn get_file_from_zip(
         &self,
-        zip_file: &String,
-        path: &String,
+        zip_file: &str,
+        path: &str,
     ) -> Result<Vec<FileContent>, Box<dyn Error>> {
-        let files = crate::zip::zip_list_http(&self.client, &zip_file).await?;
+        let files = crate::zip::zip_list_http(&self.client, zip_file).await?;
         let mut content_collection = vec![];
         println!("Files in ZIP:");
         for row in files.iter() {
@@ -140,7 +296,7 @@ impl Provider for PublicHttpEndpoint {
                 println!("Decompressed to {} bytes", uncompressed.len());
 
                 content_collection.push(FileContent {
-                    filename: path.clone(),
+                    filename: filename.clone(),
                     content: uncompressed,
                 });
             }
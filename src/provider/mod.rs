@@ -1,34 +1,75 @@
 use std::error::Error;
 
+use parquet::arrow::async_reader::AsyncFileReader;
+
 pub struct FileContent {
     pub(crate) filename: String,
     pub(crate) content: Vec<u8>,
 }
 
+pub struct ParquetSource {
+    pub(crate) filename: String,
+    pub(crate) reader: Box<dyn AsyncFileReader>,
+    // length of the file, when it's known without a request
+    pub(crate) size: Option<u64>,
+}
+
 #[async_trait::async_trait]
-pub trait Provider {
+pub trait Provider: Sync {
     // List contents of a path or URL
     async fn list_dir(
         &self,
-        cwd: &String,
-        path: &String,
+        cwd: &str,
+        path: &str,
     ) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>>;
 
+    // Paths relative to cwd of the files matching a glob pattern, e.g. ds/*/*.parquet
+    async fn find(&self, cwd: &str, pattern: &str) -> Result<Vec<String>, Box<dyn Error>>;
+
     async fn list_zip(
         &self,
-        zip_file: &String,
-        glob_match: &String,
+        zip_file: &str,
+        glob_match: &str,
     ) -> Result<Vec<Vec<String>>, Box<dyn Error>>;
 
     // get file contents
-    async fn get_file(&self, _path: &String) -> Result<Vec<FileContent>, Box<dyn Error>>;
+    async fn get_file(&self, _path: &str) -> Result<Vec<FileContent>, Box<dyn Error>>;
 
     // get file contents from zip
     async fn get_file_from_zip(
         &self,
-        zip_file: &String,
-        glob: &String,
+        zip_file: &str,
+        glob: &str,
     ) -> Result<Vec<FileContent>, Box<dyn Error>>;
+
+    // open a Parquet file for reading; providers that can seek hand back a reader
+    // which only loads the parts the decoder asks for
+    async fn open_parquet(&self, path: &str) -> Result<ParquetSource, Box<dyn Error>> {
+        let mut files = self.get_file(path).await?;
+        let file = files.pop().ok_or("No such file")?;
+        Ok(ParquetSource {
+            filename: file.filename,
+            size: Some(file.content.len() as u64),
+            reader: crate::pqt::reader_from_bytes(file.content),
+        })
+    }
+
+    // open Parquet files from zip, entries are decompressed into memory
+    async fn open_parquet_from_zip(
+        &self,
+        zip_file: &str,
+        glob: &str,
+    ) -> Result<Vec<ParquetSource>, Box<dyn Error>> {
+        let files = self.get_file_from_zip(zip_file, glob).await?;
+        Ok(files
+            .into_iter()
+            .map(|file| ParquetSource {
+                filename: file.filename,
+                size: Some(file.content.len() as u64),
+                reader: crate::pqt::reader_from_bytes(file.content),
+            })
+            .collect())
+    }
 }
 
 pub mod fs;
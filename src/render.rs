@@ -0,0 +1,219 @@
+use std::str::FromStr;
+
+use arrow::array::{Array, ArrayRef, AsArray, RecordBatch};
+use arrow::datatypes::DataType;
+use serde_json::Value;
+
+// List items and map entries shown in a flattened cell before the rest is elided
+const PREVIEW_ITEMS: usize = 3;
+
+// How `view` shows a nested column
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NestedMode {
+    // structs become dotted sub-columns, lists a length and a preview, maps key=value pairs
+    Flatten,
+    // the whole value as one JSON document
+    Json,
+    // Arrow's own one-line rendering
+    Compact,
+}
+
+impl FromStr for NestedMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flatten" | "flat" => Ok(NestedMode::Flatten),
+            "json" => Ok(NestedMode::Json),
+            "compact" => Ok(NestedMode::Compact),
+            _ => Err(format!(
+                "Unknown display mode {}, expected flatten, json or compact",
+                s
+            )),
+        }
+    }
+}
+
+// Display modes of columns by name or glob pattern, e.g. `address=json`. A pattern
+// without a column applies to all of them, later entries win.
+pub type NestedModes = Vec<(Option<String>, NestedMode)>;
+
+fn mode_for(modes: &NestedModes, path: &str) -> Option<NestedMode> {
+    modes
+        .iter()
+        .rev()
+        .find(|(pattern, _)| match pattern {
+            None => true,
+            Some(pattern) => glob::Pattern::new(pattern).is_ok_and(|p| p.matches(path)),
+        })
+        .map(|(_, mode)| *mode)
+}
+
+// Header and cells of the given rows of a batch, with nested columns expanded
+pub(crate) fn render_batch(
+    batch: &RecordBatch,
+    modes: &NestedModes,
+    rows: std::ops::Range<usize>,
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut columns = vec![];
+    for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+        let mode = mode_for(modes, field.name()).unwrap_or(NestedMode::Flatten);
+        push_columns(field.name(), array, mode, modes, &rows, &mut columns);
+    }
+
+    let header = columns.iter().map(|(name, _)| name.clone()).collect();
+    let cells = (0..rows.len())
+        .map(|row| {
+            columns
+                .iter()
+                .map(|(_, cells)| cells[row].clone())
+                .collect()
+        })
+        .collect();
+    (header, cells)
+}
+
+// Render a column into one or, for a flattened struct, several display columns
+fn push_columns(
+    name: &str,
+    array: &ArrayRef,
+    mode: NestedMode,
+    modes: &NestedModes,
+    rows: &std::ops::Range<usize>,
+    columns: &mut Vec<(String, Vec<String>)>,
+) {
+    if mode == NestedMode::Flatten
+        && let Some(array) = array.as_struct_opt()
+        && array.num_columns() > 0
+    {
+        let first = columns.len();
+        for (field, child) in array.fields().iter().zip(array.columns()) {
+            let path = format!("{}.{}", name, field.name());
+            let mode = mode_for(modes, &path).unwrap_or(mode);
+            push_columns(&path, child, mode, modes, rows, columns);
+        }
+        // A null struct shows as empty cells whatever its children hold
+        for (_, cells) in columns[first..].iter_mut() {
+            for (cell, i) in cells.iter_mut().zip(rows.clone()) {
+                if array.is_null(i) {
+                    cell.clear();
+                }
+            }
+        }
+        return;
+    }
+
+    let cells = rows.clone().map(|i| cell(array, i, mode)).collect();
+    columns.push((name.to_string(), cells));
+}
+
+fn cell(array: &dyn Array, i: usize, mode: NestedMode) -> String {
+    if array.is_null(i) {
+        return String::new();
+    }
+    match mode {
+        NestedMode::Json => json_value(array, i).to_string(),
+        NestedMode::Compact => display(array, i),
+        NestedMode::Flatten => match list_items(array, i) {
+            Some(items) => format!("[{}] {}", items.len(), preview_items(&items)),
+            None => preview(array, i),
+        },
+    }
+}
+
+// A value inside a flattened cell: lists in brackets, structs and maps as key=value pairs
+fn preview(array: &dyn Array, i: usize) -> String {
+    if array.is_null(i) {
+        return "null".to_string();
+    }
+    if let Some(items) = list_items(array, i) {
+        return format!("[{}]", preview_items(&items));
+    }
+    if let Some(map) = array.as_map_opt() {
+        let entries = map.value(i);
+        let pairs = join_preview(entries.len(), |e| {
+            format!(
+                "{}={}",
+                preview(entries.column(0), e),
+                preview(entries.column(1), e)
+            )
+        });
+        return format!("{{{}}}", pairs);
+    }
+    if let Some(array) = array.as_struct_opt() {
+        let pairs: Vec<String> = array
+            .fields()
+            .iter()
+            .zip(array.columns())
+            .map(|(field, child)| format!("{}={}", field.name(), preview(child, i)))
+            .collect();
+        return format!("{{{}}}", pairs.join(", "));
+    }
+    display(array, i)
+}
+
+fn preview_items(items: &ArrayRef) -> String {
+    join_preview(items.len(), |i| preview(items, i))
+}
+
+// Join the first few of len rendered parts, eliding the rest
+fn join_preview(len: usize, part: impl Fn(usize) -> String) -> String {
+    let shown = len.min(PREVIEW_ITEMS);
+    let parts: Vec<String> = (0..shown).map(part).collect();
+    parts.join(", ") + if len > shown { ", ..." } else { "" }
+}
+
+// Items of a list value, None when the array isn't a list
+fn list_items(array: &dyn Array, i: usize) -> Option<ArrayRef> {
+    match array.data_type() {
+        DataType::List(_) => Some(array.as_list::<i32>().value(i)),
+        DataType::LargeList(_) => Some(array.as_list::<i64>().value(i)),
+        DataType::FixedSizeList(_, _) => Some(array.as_fixed_size_list().value(i)),
+        _ => None,
+    }
+}
+
+fn json_value(array: &dyn Array, i: usize) -> Value {
+    if array.is_null(i) {
+        return Value::Null;
+    }
+    if let Some(items) = list_items(array, i) {
+        return Value::Array((0..items.len()).map(|e| json_value(&items, e)).collect());
+    }
+    if let Some(map) = array.as_map_opt() {
+        let entries = map.value(i);
+        return Value::Object(
+            (0..entries.len())
+                .map(|e| {
+                    (
+                        display(entries.column(0), e),
+                        json_value(entries.column(1), e),
+                    )
+                })
+                .collect(),
+        );
+    }
+    if let Some(array) = array.as_struct_opt() {
+        return Value::Object(
+            array
+                .fields()
+                .iter()
+                .zip(array.columns())
+                .map(|(field, child)| (field.name().clone(), json_value(child, i)))
+                .collect(),
+        );
+    }
+    let text = display(array, i);
+    let data_type = array.data_type();
+    if data_type.is_numeric() || *data_type == DataType::Boolean {
+        // Arrow prints numbers and booleans the way JSON spells them, except NaN and inf
+        if let Ok(value) = serde_json::from_str::<Value>(&text) {
+            return value;
+        }
+    }
+    Value::String(text)
+}
+
+fn display(array: &dyn Array, i: usize) -> String {
+    arrow::util::display::array_value_to_string(array, i).unwrap_or_default()
+}
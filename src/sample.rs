@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, RecordBatch, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use parquet::arrow::arrow_reader::{RowSelection, RowSelector};
+
+use crate::dataset::{Dataset, FILE_COLUMN};
+use crate::pqt::{ViewOptions, print_batches};
+use crate::utils::SplitMix64;
+
+// Column with the number of each sampled row in its file, counting from 1
+const ROW_COLUMN: &str = "_row";
+
+// `count` distinct numbers from 0..total, each set of them as likely as any other
+// (Floyd's algorithm), in increasing order
+fn choose_rows(total: u64, count: u64, rng: &mut SplitMix64) -> BTreeSet<u64> {
+    let mut chosen = BTreeSet::new();
+    for j in total - count..total {
+        let row = rng.below(j + 1);
+        if !chosen.insert(row) {
+            chosen.insert(j);
+        }
+    }
+    chosen
+}
+
+// Rows picked uniformly at random from all files of the dataset. Picking rows of the
+// whole dataset picks each row group as often as its share of the rows; only the
+// row groups picked are read, and of those only the pages holding the picked rows.
+pub(crate) async fn parquet_sample(
+    dataset: Dataset<'_>,
+    count: usize,
+    seed: u64,
+    options: &ViewOptions,
+) -> Result<(), Box<dyn Error>> {
+    let files = &dataset.files;
+    let total: u64 = files
+        .iter()
+        .map(|file| file.metadata.metadata().file_metadata().num_rows() as u64)
+        .sum();
+    if total == 0 {
+        println!("No rows to sample");
+        return Ok(());
+    }
+    let mut rng = SplitMix64::new(seed);
+    let chosen = choose_rows(total, (count as u64).min(total), &mut rng);
+
+    let show_file = files.len() > 1;
+    let mut fields = vec![Field::new(ROW_COLUMN, DataType::UInt64, false)];
+    if show_file {
+        fields.push(Field::new(FILE_COLUMN, DataType::Utf8, false));
+    }
+    fields.extend(dataset.schema.fields().iter().map(|f| f.as_ref().clone()));
+    let sample_schema = Arc::new(Schema::new(fields));
+
+    let mut batches = vec![];
+    let (mut first_row, mut row_groups_read, mut row_groups) = (0, 0, 0);
+    for file in files {
+        let metadata = file.metadata.metadata();
+        let file_rows = metadata.file_metadata().num_rows() as u64;
+        let end = first_row + file_rows;
+        // the picked rows of this file, numbered within it
+        let rows: Vec<u64> = chosen
+            .range(first_row..end)
+            .map(|row| row - first_row)
+            .collect();
+        first_row = end;
+        row_groups += metadata.num_row_groups();
+        if rows.is_empty() {
+            continue;
+        }
+
+        let mut selected = vec![];
+        let mut selectors = vec![];
+        let mut rows_iter = rows.iter().peekable();
+        let mut rg_start = 0;
+        for (idx, rg) in metadata.row_groups().iter().enumerate() {
+            let rg_end = rg_start + rg.num_rows() as u64;
+            let mut position = rg_start;
+            while let Some(row) = rows_iter.next_if(|row| **row < rg_end) {
+                selectors.push(RowSelector::skip((row - position) as usize));
+                selectors.push(RowSelector::select(1));
+                position = row + 1;
+            }
+            if position > rg_start {
+                selectors.push(RowSelector::skip((rg_end - position) as usize));
+                selected.push(idx);
+            }
+            rg_start = rg_end;
+        }
+        row_groups_read += selected.len();
+
+        let mut stream = dataset
+            .reader(file)
+            .await?
+            .with_row_groups(selected)
+            .with_row_selection(RowSelection::from(selectors))
+            .with_batch_size(8192)
+            .build()?;
+        let mut numbers = rows.iter();
+        while let Some(batch) = stream.try_next().await? {
+            let batch = file.conform(&batch, &dataset.schema)?;
+            let row_numbers: UInt64Array = numbers
+                .by_ref()
+                .take(batch.num_rows())
+                .map(|row| row + 1)
+                .collect();
+            let mut columns: Vec<ArrayRef> = vec![Arc::new(row_numbers)];
+            if show_file {
+                columns.push(Arc::new(StringArray::from(vec![
+                    file.path.as_str();
+                    batch.num_rows()
+                ])));
+            }
+            columns.extend(batch.columns().iter().cloned());
+            batches.push(RecordBatch::try_new(sample_schema.clone(), columns)?);
+        }
+    }
+
+    let options = ViewOptions {
+        max_rows: chosen.len(),
+        ..options.clone()
+    };
+    print_batches(&batches, &options);
+    println!(
+        "{} of {} rows, read from {} of {} row groups (seed {})",
+        chosen.len(),
+        total,
+        row_groups_read,
+        row_groups,
+        seed
+    );
+    Ok(())
+}
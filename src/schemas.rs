@@ -0,0 +1,323 @@
+use std::error::Error;
+
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::basic::ConvertedType;
+use parquet::schema::types::{Type, TypePtr};
+
+use crate::pqt::read_metadata;
+use crate::utils::{compute_col_max_len, print_rows_left};
+
+// Files listed per schema version by `schemas`, the rest are only counted
+const MAX_LISTED_FILES: usize = 20;
+
+// A field of a Parquet schema, found by its dotted path
+#[derive(Clone, Debug, PartialEq)]
+struct SchemaField {
+    path: String,
+    // e.g. INT64, BYTE_ARRAY String, group List
+    type_name: String,
+    repetition: String,
+}
+
+impl SchemaField {
+    fn parent(&self) -> &str {
+        self.path.rsplit_once('.').map_or("", |(parent, _)| parent)
+    }
+
+    fn describe(&self) -> String {
+        format!("{} {}", self.type_name, self.repetition)
+    }
+}
+
+// All fields of a schema, every group followed by its children
+fn schema_fields(root: &Type) -> Vec<SchemaField> {
+    let mut fields = vec![];
+    for field in root.get_fields() {
+        collect_fields(field, "", &mut fields);
+    }
+    fields
+}
+
+fn collect_fields(node: &Type, parent: &str, fields: &mut Vec<SchemaField>) {
+    let info = node.get_basic_info();
+    let path = if parent.is_empty() {
+        info.name().to_string()
+    } else {
+        format!("{}.{}", parent, info.name())
+    };
+    let mut type_name = match node {
+        Type::GroupType { .. } => "group".to_string(),
+        Type::PrimitiveType { type_length, .. } => match node.get_physical_type() {
+            parquet::basic::Type::FIXED_LEN_BYTE_ARRAY => {
+                format!("FIXED_LEN_BYTE_ARRAY({type_length})")
+            }
+            physical_type => physical_type.to_string(),
+        },
+    };
+    match (info.logical_type_ref(), info.converted_type()) {
+        (Some(logical_type), _) => type_name += &format!(" {logical_type:?}"),
+        (None, ConvertedType::NONE) => {}
+        (None, converted_type) => type_name += &format!(" {converted_type}"),
+    }
+    let repetition = if info.has_repetition() {
+        info.repetition().to_string()
+    } else {
+        String::new()
+    };
+    fields.push(SchemaField {
+        path: path.clone(),
+        type_name,
+        repetition,
+    });
+    if node.is_group() {
+        for child in node.get_fields() {
+            collect_fields(child, &path, fields);
+        }
+    }
+}
+
+async fn read_schema<T: AsyncFileReader>(mut reader: T) -> Result<TypePtr, Box<dyn Error>> {
+    let metadata = read_metadata(&mut reader, false).await?;
+    Ok(metadata.file_metadata().schema_descr().root_schema_ptr())
+}
+
+// How the fields of schema b differ from those of schema a, as rows of
+// change, field, a and b. Fields below an added or removed group aren't listed
+// on their own.
+fn diff_fields(a: &[SchemaField], b: &[SchemaField]) -> Vec<Vec<String>> {
+    let find = |fields: &[SchemaField], path: &str| fields.iter().position(|f| f.path == path);
+    let mut changes = vec![];
+    for field in a {
+        let parent_removed = !field.parent().is_empty() && find(b, field.parent()).is_none();
+        match find(b, &field.path) {
+            None if !parent_removed => changes.push(vec![
+                "removed".to_string(),
+                field.path.clone(),
+                field.describe(),
+                String::new(),
+            ]),
+            None => {}
+            Some(idx) => {
+                let other = &b[idx];
+                if field.type_name != other.type_name {
+                    changes.push(vec![
+                        "type changed".to_string(),
+                        field.path.clone(),
+                        field.type_name.clone(),
+                        other.type_name.clone(),
+                    ]);
+                }
+                if field.repetition != other.repetition {
+                    changes.push(vec![
+                        "nullability changed".to_string(),
+                        field.path.clone(),
+                        field.repetition.clone(),
+                        other.repetition.clone(),
+                    ]);
+                }
+            }
+        }
+    }
+    for field in b {
+        let parent_added = !field.parent().is_empty() && find(a, field.parent()).is_none();
+        if find(a, &field.path).is_none() && !parent_added {
+            changes.push(vec![
+                "added".to_string(),
+                field.path.clone(),
+                String::new(),
+                field.describe(),
+            ]);
+        }
+    }
+
+    // Fields both schemas have, which have moved relative to their siblings
+    let siblings = |fields: &[SchemaField], other: &[SchemaField], parent: &str| -> Vec<String> {
+        fields
+            .iter()
+            .filter(|f| f.parent() == parent && find(other, &f.path).is_some())
+            .map(|f| f.path.clone())
+            .collect()
+    };
+    let mut parents = vec![String::new()];
+    parents.extend(
+        a.iter()
+            .filter(|f| f.type_name.starts_with("group") && find(b, &f.path).is_some())
+            .map(|f| f.path.clone()),
+    );
+    for parent in parents {
+        let in_a = siblings(a, b, &parent);
+        let in_b = siblings(b, a, &parent);
+        // The longest run of fields keeping their order stays put, the others moved
+        let kept = longest_common_subsequence(&in_a, &in_b);
+        for (position, path) in in_a.iter().enumerate() {
+            if kept.contains(&path) {
+                continue;
+            }
+            let new_position = in_b.iter().position(|p| p == path).unwrap_or(position);
+            changes.push(vec![
+                "reordered".to_string(),
+                path.clone(),
+                format!("position {}", position + 1),
+                format!("position {}", new_position + 1),
+            ]);
+        }
+    }
+    changes
+}
+
+fn longest_common_subsequence<'a>(a: &'a [String], b: &[String]) -> Vec<&'a String> {
+    // lengths[i][j] is the length for a[i..] and b[j..]
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut common = vec![];
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            common.push(&a[i]);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+fn print_changes(changes: Vec<Vec<String>>, a: &str, b: &str) {
+    if changes.is_empty() {
+        println!("The schemas are the same");
+        return;
+    }
+    let count = changes.len();
+    let mut rows = vec![vec![
+        "Change".to_string(),
+        "Field".to_string(),
+        a.to_string(),
+        b.to_string(),
+    ]];
+    rows.extend(changes);
+    let col_max_len = compute_col_max_len(&rows);
+    print_rows_left(&rows, &col_max_len, true);
+    println!(
+        "{} {}",
+        count,
+        if count == 1 {
+            "difference"
+        } else {
+            "differences"
+        }
+    );
+}
+
+// Compare the schemas of two files field by field, nested fields included
+pub async fn parquet_schemadiff<A: AsyncFileReader, B: AsyncFileReader>(
+    a: (&str, A),
+    b: (&str, B),
+) -> Result<(), Box<dyn Error>> {
+    let fields_a = schema_fields(read_schema(a.1).await?.as_ref());
+    let fields_b = schema_fields(read_schema(b.1).await?.as_ref());
+    print_changes(diff_fields(&fields_a, &fields_b), a.0, b.0);
+    Ok(())
+}
+
+// Files grouped by their schema, one file read at a time
+#[derive(Default)]
+pub struct SchemaVersions {
+    versions: Vec<(Vec<SchemaField>, Vec<String>)>,
+    unreadable: Vec<Vec<String>>,
+}
+
+impl SchemaVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Read the schema of a file and count it with the files of the same schema
+    pub async fn add<T: AsyncFileReader>(&mut self, filename: String, reader: T) {
+        let fields = match read_schema(reader).await {
+            Ok(schema) => schema_fields(&schema),
+            Err(e) => {
+                self.unreadable(filename, e.as_ref());
+                return;
+            }
+        };
+        match self.versions.iter_mut().find(|(f, _)| *f == fields) {
+            Some((_, filenames)) => filenames.push(filename),
+            None => self.versions.push((fields, vec![filename])),
+        }
+    }
+
+    pub fn unreadable(&mut self, filename: String, e: &dyn Error) {
+        self.unreadable.push(vec![filename, e.to_string()]);
+    }
+
+    // The versions, the most common one first, and how each of the others differs
+    // from it
+    pub fn print(self) {
+        let Self {
+            mut versions,
+            unreadable,
+        } = self;
+        // stable, so versions with as many files stay in the order they were found
+        versions.sort_by_key(|(_, filenames)| std::cmp::Reverse(filenames.len()));
+
+        let mut rows = vec![vec![
+            "Version".to_string(),
+            "Files".to_string(),
+            "Fields".to_string(),
+            "First file".to_string(),
+        ]];
+        for (idx, (fields, filenames)) in versions.iter().enumerate() {
+            rows.push(vec![
+                (idx + 1).to_string(),
+                filenames.len().to_string(),
+                fields.len().to_string(),
+                filenames[0].clone(),
+            ]);
+        }
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows_left(&rows, &col_max_len, true);
+
+        for (idx, (fields, filenames)) in versions.iter().enumerate() {
+            println!();
+            println!(
+                "Version {}: {} {}",
+                idx + 1,
+                filenames.len(),
+                if filenames.len() == 1 {
+                    "file"
+                } else {
+                    "files"
+                }
+            );
+            for filename in filenames.iter().take(MAX_LISTED_FILES) {
+                println!("  {}", filename);
+            }
+            if filenames.len() > MAX_LISTED_FILES {
+                println!("  ... and {} more", filenames.len() - MAX_LISTED_FILES);
+            }
+            if idx > 0 {
+                let changes = diff_fields(&versions[0].0, fields);
+                print_changes(changes, "Version 1", &format!("Version {}", idx + 1));
+            }
+        }
+        if !unreadable.is_empty() {
+            println!();
+            println!("Unreadable files: {}", unreadable.len());
+            let mut rows = vec![vec!["File".to_string(), "Error".to_string()]];
+            rows.extend(unreadable);
+            let col_max_len = compute_col_max_len(&rows);
+            print_rows_left(&rows, &col_max_len, true);
+        }
+    }
+}
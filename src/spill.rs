@@ -0,0 +1,227 @@
+use std::error::Error;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use arrow::array::{RecordBatch, UInt32Array};
+use arrow::compute::take_record_batch;
+use arrow::datatypes::SchemaRef;
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::row::Rows;
+
+// Numbers the spill files of this process
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// Partitions of a spill, each open for writing until the first is read back. Well
+// below the usual limit of 1024 open files, with a and b of diff spilled at once.
+pub(crate) const MAX_PARTITIONS: usize = 64;
+
+// How many times the rows of a partition too big for memory are partitioned again.
+// Past that its rows share too few keys to split and it's held whole.
+pub(crate) const MAX_SPILL_LEVEL: usize = 3;
+
+// Batches that don't fit in memory, written to a temporary Arrow IPC file and read
+// back in the order they were written. The file is removed when this is dropped.
+pub(crate) struct SpillFile {
+    path: PathBuf,
+    writer: Option<FileWriter<BufWriter<File>>>,
+    pub(crate) rows: usize,
+    // memory the batches written took
+    pub(crate) bytes: usize,
+}
+
+impl SpillFile {
+    pub(crate) fn new(schema: &SchemaRef) -> Result<Self, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "parquet-viewer-{}-{}.arrow",
+            std::process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(Self {
+            writer: Some(FileWriter::try_new(file, schema)?),
+            path,
+            rows: 0,
+            bytes: 0,
+        })
+    }
+
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> Result<(), Box<dyn Error>> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or("The spill file was already read")?;
+        writer.write(batch)?;
+        self.rows += batch.num_rows();
+        self.bytes += batch.get_array_memory_size();
+        Ok(())
+    }
+
+    // Stop writing, which closes the file until it's read
+    pub(crate) fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+
+    // Stop writing and read the batches back
+    pub(crate) fn read(&mut self) -> Result<FileReader<BufReader<File>>, Box<dyn Error>> {
+        self.finish()?;
+        let file = BufReader::new(File::open(&self.path)?);
+        Ok(FileReader::try_new(file, None)?)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        self.writer.take();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Rows split into spill files by the hash of their key, so rows with the same key
+// from any number of batches end up in the same partition. Partitions of the same
+// count and level split keys the same way; a partition split again is at the next
+// level, which hashes differently.
+pub(crate) struct Partitions {
+    files: Vec<Option<SpillFile>>,
+    level: usize,
+}
+
+impl Partitions {
+    pub(crate) fn new(count: usize, level: usize) -> Self {
+        Self {
+            files: (0..count).map(|_| None).collect(),
+            level,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub(crate) fn level(&self) -> usize {
+        self.level
+    }
+
+    // Memory the rows of a partition took when they were written
+    pub(crate) fn bytes(&self, idx: usize) -> usize {
+        self.files[idx].as_ref().map_or(0, |file| file.bytes)
+    }
+
+    // Write every row of the batch to the partition of its key
+    pub(crate) fn write(&mut self, batch: &RecordBatch, keys: &Rows) -> Result<(), Box<dyn Error>> {
+        let mut indices: Vec<Vec<u32>> = vec![vec![]; self.files.len()];
+        for (i, key) in keys.iter().enumerate() {
+            indices[partition(key.as_ref(), self.files.len(), self.level)].push(i as u32);
+        }
+        for (idx, rows) in indices.into_iter().enumerate() {
+            if rows.is_empty() {
+                continue;
+            }
+            let part = take_record_batch(batch, &UInt32Array::from(rows))?;
+            if self.files[idx].is_none() {
+                self.files[idx] = Some(SpillFile::new(&batch.schema())?);
+            }
+            self.files[idx]
+                .as_mut()
+                .expect("created above")
+                .write(&part)?;
+        }
+        Ok(())
+    }
+
+    // The batches of a partition, None when no row fell into it. Each partition can
+    // be read once, its file is removed afterwards. Reading closes the files of all
+    // partitions for writing, so only the one read is open.
+    pub(crate) fn take(
+        &mut self,
+        idx: usize,
+    ) -> Result<Option<FileReader<BufReader<File>>>, Box<dyn Error>> {
+        for file in self.files.iter_mut().flatten() {
+            file.finish()?;
+        }
+        match self.files[idx].take() {
+            Some(mut file) => Ok(Some(file.read()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+// The partition of a key at a level, the same for equal keys
+pub(crate) fn partition(key: &[u8], partitions: usize, level: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    level.hash(&mut hasher);
+    key.hash(&mut hasher);
+    (hasher.finish() % partitions as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{AsArray, Int64Array};
+    use arrow::datatypes::{DataType, Field, Int64Type, Schema};
+    use arrow::row::{RowConverter, SortField};
+
+    use super::*;
+
+    fn batch(ids: Vec<i64>) -> (RecordBatch, Rows) {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(ids))]).unwrap();
+        let keys = RowConverter::new(vec![SortField::new(DataType::Int64)])
+            .unwrap()
+            .convert_columns(batch.columns())
+            .unwrap();
+        (batch, keys)
+    }
+
+    // The ids in each partition
+    fn read_back(partitions: &mut Partitions) -> Vec<Vec<i64>> {
+        (0..partitions.len())
+            .map(|idx| {
+                let mut ids = vec![];
+                for batch in partitions.take(idx).unwrap().into_iter().flatten() {
+                    let batch = batch.unwrap();
+                    ids.extend(batch.column(0).as_primitive::<Int64Type>().values());
+                }
+                ids
+            })
+            .collect()
+    }
+
+    #[test]
+    fn equal_keys_share_a_partition() {
+        let mut partitions = Partitions::new(4, 0);
+        for _ in 0..3 {
+            let (batch, keys) = batch((0..100).collect());
+            partitions.write(&batch, &keys).unwrap();
+        }
+        let parts = read_back(&mut partitions);
+        assert_eq!(parts.iter().map(Vec::len).sum::<usize>(), 300);
+        for id in 0..100 {
+            let holding: Vec<_> = parts.iter().filter(|ids| ids.contains(&id)).collect();
+            assert_eq!(holding.len(), 1);
+            assert_eq!(holding[0].iter().filter(|i| **i == id).count(), 3);
+        }
+        // each partition can only be read once
+        assert!(partitions.take(0).unwrap().is_none());
+    }
+
+    #[test]
+    fn levels_split_keys_differently() {
+        let (batch, keys) = batch((0..1000).collect());
+        let mut level_0 = Partitions::new(2, 0);
+        level_0.write(&batch, &keys).unwrap();
+        let first = read_back(&mut level_0).swap_remove(0);
+        // the keys of one partition, split again at the next level, spread over both
+        let (batch, keys) = self::batch(first);
+        let mut level_1 = Partitions::new(2, 1);
+        level_1.write(&batch, &keys).unwrap();
+        assert!(read_back(&mut level_1).iter().all(|ids| !ids.is_empty()));
+    }
+}
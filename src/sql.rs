@@ -0,0 +1,615 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, RecordBatch};
+use arrow::compute::{cast, concat_batches};
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::row::{RowConverter, Rows, SortField};
+use futures::TryStreamExt;
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use parquet::arrow::arrow_reader::ArrowReaderOptions;
+
+use crate::aggregate::{AggFunc, Aggregate, HashAggregation};
+use crate::pqt::{apply_filter, projection_mask};
+use crate::predicate::{Predicate, Token, column_by_path, tokenize};
+use crate::provider::ParquetSource;
+use crate::topn::{TopRows, sort_options};
+
+// A query over Parquet files:
+//
+//   SELECT [DISTINCT] a, b AS c, count(*), sum(x) FROM 'data/*.parquet'
+//   [WHERE <filter>] [GROUP BY a, b] [ORDER BY c DESC, 1] [LIMIT n]
+//
+// Columns are plain or dotted paths, the aggregates are count, sum, avg, min and max,
+// and the WHERE clause is a filter as `view --where` takes it.
+#[derive(Debug)]
+pub struct Query {
+    items: Vec<SelectItem>,
+    pub from: String,
+    filter: Option<Predicate>,
+    group_by: Vec<String>,
+    order_by: Vec<OrderBy>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug)]
+enum SelectItem {
+    Wildcard,
+    Column {
+        path: String,
+        alias: Option<String>,
+    },
+    Aggregate {
+        aggregate: Aggregate,
+        alias: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+struct OrderBy {
+    key: OrderKey,
+    descending: bool,
+}
+
+#[derive(Debug)]
+enum OrderKey {
+    // 1-based position in the select list
+    Position(usize),
+    Name(String),
+}
+
+impl SelectItem {
+    // Column of the intermediate result the item reads
+    fn source(&self) -> String {
+        match self {
+            SelectItem::Wildcard => "*".to_string(),
+            SelectItem::Column { path, .. } => path.clone(),
+            SelectItem::Aggregate { aggregate, .. } => aggregate.name(),
+        }
+    }
+
+    fn output_name(&self) -> String {
+        match self {
+            SelectItem::Column {
+                alias: Some(alias), ..
+            }
+            | SelectItem::Aggregate {
+                alias: Some(alias), ..
+            } => alias.clone(),
+            _ => self.source(),
+        }
+    }
+}
+
+const CLAUSE_KEYWORDS: [&str; 4] = ["where", "group", "order", "limit"];
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let input = input.trim().trim_end_matches(';');
+        let mut parser = QueryParser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+        parser.expect_keyword("select")?;
+        let distinct = parser.keyword("distinct");
+
+        let mut items = vec![parser.parse_item()?];
+        while parser.peek() == Some(&Token::Comma) {
+            parser.pos += 1;
+            items.push(parser.parse_item()?);
+        }
+
+        parser.expect_keyword("from")?;
+        let from = match parser.next() {
+            Some(Token::Str(path) | Token::Ident(path)) => path,
+            _ => {
+                return Err("Expected a quoted path after FROM, e.g. FROM 'data/*.parquet'".into());
+            }
+        };
+
+        let filter = if parser.keyword("where") {
+            // The filter runs up to the next clause, outside of parentheses
+            let start = parser.pos;
+            let mut depth = 0;
+            while let Some(token) = parser.peek() {
+                match token {
+                    Token::LParen => depth += 1,
+                    Token::RParen => depth -= 1,
+                    Token::Ident(word)
+                        if depth == 0
+                            && CLAUSE_KEYWORDS.contains(&word.to_ascii_lowercase().as_str()) =>
+                    {
+                        break;
+                    }
+                    _ => {}
+                }
+                parser.pos += 1;
+            }
+            Some(Predicate::from_tokens(
+                parser.tokens[start..parser.pos].to_vec(),
+            )?)
+        } else {
+            None
+        };
+
+        let mut group_by = vec![];
+        if parser.keyword("group") {
+            parser.expect_keyword("by")?;
+            group_by.push(parser.parse_name()?);
+            while parser.peek() == Some(&Token::Comma) {
+                parser.pos += 1;
+                group_by.push(parser.parse_name()?);
+            }
+        }
+
+        let mut order_by = vec![];
+        if parser.keyword("order") {
+            parser.expect_keyword("by")?;
+            loop {
+                let key = match parser.peek() {
+                    Some(Token::Number(n)) => {
+                        let position = n
+                            .parse()
+                            .map_err(|_| format!("Invalid ORDER BY position {}", n))?;
+                        parser.pos += 1;
+                        OrderKey::Position(position)
+                    }
+                    _ => match parser.parse_expression()? {
+                        SelectItem::Aggregate { aggregate, .. } => OrderKey::Name(aggregate.name()),
+                        item => OrderKey::Name(item.source()),
+                    },
+                };
+                let descending = parser.keyword("desc");
+                if !descending {
+                    parser.keyword("asc");
+                }
+                order_by.push(OrderBy { key, descending });
+                if parser.peek() != Some(&Token::Comma) {
+                    break;
+                }
+                parser.pos += 1;
+            }
+        }
+
+        let limit = if parser.keyword("limit") {
+            match parser.next() {
+                Some(Token::Number(n)) => {
+                    Some(n.parse().map_err(|_| format!("Invalid LIMIT {}", n))?)
+                }
+                _ => return Err("Expected a number after LIMIT".into()),
+            }
+        } else {
+            None
+        };
+
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {:?} in query", token).into());
+        }
+
+        // SELECT DISTINCT a, b is grouping by a, b without aggregates
+        if distinct && group_by.is_empty() {
+            for item in items.iter() {
+                match item {
+                    SelectItem::Column { path, .. } => group_by.push(path.clone()),
+                    SelectItem::Wildcard => {
+                        return Err("SELECT DISTINCT needs the columns listed".into());
+                    }
+                    SelectItem::Aggregate { .. } => {}
+                }
+            }
+        }
+
+        Ok(Self {
+            items,
+            from,
+            filter,
+            group_by,
+            order_by,
+            limit,
+        })
+    }
+
+    fn is_aggregate(&self) -> bool {
+        !self.group_by.is_empty()
+            || self
+                .items
+                .iter()
+                .any(|item| matches!(item, SelectItem::Aggregate { .. }))
+    }
+
+    // Aggregates the select list and ORDER BY need, each one once
+    fn aggregates(&self) -> Vec<Aggregate> {
+        let mut aggregates: Vec<Aggregate> = vec![];
+        for item in self.items.iter() {
+            if let SelectItem::Aggregate { aggregate, .. } = item
+                && !aggregates.contains(aggregate)
+            {
+                aggregates.push(aggregate.clone());
+            }
+        }
+        for order in self.order_by.iter() {
+            if let OrderKey::Name(name) = &order.key
+                && let Some(aggregate) = parse_aggregate_name(name)
+                && !aggregates.contains(&aggregate)
+                && !self.items.iter().any(|item| item.output_name() == *name)
+            {
+                aggregates.push(aggregate);
+            }
+        }
+        aggregates
+    }
+
+    // Run the query over the files, reading only the columns it needs and skipping
+    // row groups and pages the WHERE clause rules out. Without LIMIT only the first
+    // `cap` rows are kept; the rows of the result are counted all the same and returned
+    // next to it. With ORDER BY the rows are kept in a heap of as many as are returned.
+    pub async fn execute(
+        &self,
+        sources: Vec<ParquetSource>,
+        cap: usize,
+    ) -> Result<(RecordBatch, usize), Box<dyn Error>> {
+        let limit = self.limit.unwrap_or(cap);
+        let aggregate = self.is_aggregate();
+        let mut columns = vec![];
+        if aggregate {
+            for item in self.items.iter() {
+                match item {
+                    SelectItem::Wildcard => {
+                        return Err("SELECT * can't be combined with aggregates or GROUP BY".into());
+                    }
+                    SelectItem::Column { path, .. } if !self.group_by.contains(path) => {
+                        return Err(format!(
+                            "Column {} must appear in GROUP BY or be aggregated",
+                            path
+                        )
+                        .into());
+                    }
+                    _ => {}
+                }
+            }
+            columns.extend(self.group_by.iter().cloned());
+            columns.extend(self.aggregates().into_iter().filter_map(|a| a.column));
+        } else if !self
+            .items
+            .iter()
+            .any(|item| matches!(item, SelectItem::Wildcard))
+        {
+            columns.extend(self.items.iter().map(SelectItem::source));
+            columns.extend(self.order_names());
+        }
+        let columns = if columns.is_empty() && !aggregate {
+            None
+        } else {
+            Some(columns)
+        };
+
+        let mut aggregation = HashAggregation::new(self.group_by.clone(), self.aggregates());
+        // the first rows without ORDER BY, the top ones with it
+        let mut batches = vec![];
+        let mut top = TopRows::new(limit);
+        let mut schema: Option<SchemaRef> = None;
+        let mut pushed = 0;
+        let mut rows = 0;
+        for source in sources {
+            let reader_options = ArrowReaderOptions::new().with_page_index(self.filter.is_some());
+            let mut builder =
+                ParquetRecordBatchStreamBuilder::new_with_options(source.reader, reader_options)
+                    .await?;
+            if let Some(columns) = &columns {
+                let mask = projection_mask(builder.parquet_schema(), columns)
+                    .map_err(|e| format!("{}: {}", source.filename, e))?;
+                builder = builder.with_projection(mask);
+            }
+            if let Some(filter) = &self.filter {
+                let row_groups: Vec<usize> = (0..builder.metadata().num_row_groups()).collect();
+                builder = apply_filter(builder, filter, &row_groups)?;
+            }
+            let mut stream = builder.with_batch_size(8192).build()?;
+            let empty = RecordBatch::new_empty(stream.schema().clone());
+            if aggregate {
+                // Sets up the result types even when no row matches
+                aggregation.update(&empty)?;
+            } else if schema.is_none() {
+                // the columns of the result even when no row matches
+                schema = Some(self.select_columns(&empty)?.schema());
+            }
+            while let Some(batch) = stream.try_next().await? {
+                if aggregate {
+                    aggregation.update(&batch)?;
+                    continue;
+                }
+                if !self.order_by.is_empty() {
+                    let selected = self.select_columns(&batch)?;
+                    let schema = schema.as_ref().expect("set up for the first file");
+                    let selected = cast_batch(&selected, schema)?;
+                    top.push(&selected, &self.order_keys(&selected)?, pushed, 0)?;
+                    pushed += 1;
+                } else if rows < limit {
+                    // Without ORDER BY the first rows are the result, the rest are only counted
+                    batches.push(self.select_columns(&batch)?);
+                }
+                rows += batch.num_rows();
+                if self.order_by.is_empty() && self.limit.is_some_and(|limit| rows >= limit) {
+                    break;
+                }
+            }
+            if !aggregate
+                && self.order_by.is_empty()
+                && self.limit.is_some_and(|limit| rows >= limit)
+            {
+                break;
+            }
+        }
+
+        let result = if aggregate {
+            let result = aggregation.finish()?;
+            rows = result.num_rows();
+            if self.order_by.is_empty() {
+                result
+            } else {
+                let mut top = TopRows::new(limit);
+                top.push(&result, &self.order_keys(&result)?, 0, 0)?;
+                top.finish()?
+                    .unwrap_or_else(|| RecordBatch::new_empty(result.schema()))
+            }
+        } else if !self.order_by.is_empty() {
+            match (top.finish()?, schema) {
+                (Some(result), _) => result,
+                (None, Some(schema)) => RecordBatch::new_empty(schema),
+                (None, None) => concat_cast(vec![])?,
+            }
+        } else {
+            match schema {
+                Some(schema) if batches.is_empty() => RecordBatch::new_empty(schema),
+                _ => concat_cast(batches)?,
+            }
+        };
+        let total = match self.limit {
+            Some(limit) => limit.min(rows),
+            None => rows,
+        };
+        let result = result.slice(0, limit.min(result.num_rows()));
+        Ok((self.project(&result)?, total))
+    }
+
+    // Columns ORDER BY refers to by name which aren't aliases
+    fn order_names(&self) -> Vec<String> {
+        self.order_by
+            .iter()
+            .filter_map(|order| match &order.key {
+                OrderKey::Name(name) if !self.items.iter().any(|i| i.output_name() == *name) => {
+                    Some(name.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // The columns of a batch the query reads, named by their paths
+    fn select_columns(&self, batch: &RecordBatch) -> Result<RecordBatch, Box<dyn Error>> {
+        let mut paths: Vec<String> = vec![];
+        for item in self.items.iter() {
+            match item {
+                SelectItem::Wildcard => {
+                    paths.extend(batch.schema().fields().iter().map(|f| f.name().clone()))
+                }
+                item => paths.push(item.source()),
+            }
+        }
+        paths.extend(self.order_names());
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for path in paths {
+            if fields.iter().any(|f: &Field| *f.name() == path) {
+                continue;
+            }
+            let column = column_by_path(batch, &path)?;
+            fields.push(Field::new(path, column.data_type().clone(), true));
+            columns.push(column);
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    // Index of the intermediate column an ORDER BY key refers to
+    fn order_column(&self, key: &OrderKey, schema: &Schema) -> Result<usize, Box<dyn Error>> {
+        let name = match key {
+            OrderKey::Position(position) => match self.items.get(position.wrapping_sub(1)) {
+                Some(SelectItem::Wildcard) | None => {
+                    return Err(format!(
+                        "ORDER BY position {} is not in the select list",
+                        position
+                    )
+                    .into());
+                }
+                Some(item) => item.source(),
+            },
+            OrderKey::Name(name) => self
+                .items
+                .iter()
+                .find(|item| item.output_name() == *name)
+                .map(SelectItem::source)
+                .unwrap_or_else(|| name.clone()),
+        };
+        schema
+            .index_of(&name)
+            .map_err(|_| format!("Can't order by {}", name).into())
+    }
+
+    // The ORDER BY keys of the rows, in the row order `view --order-by` sorts by
+    fn order_keys(&self, batch: &RecordBatch) -> Result<Rows, Box<dyn Error>> {
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for order in self.order_by.iter() {
+            let column = batch.column(self.order_column(&order.key, &batch.schema())?);
+            fields.push(SortField::new_with_options(
+                column.data_type().clone(),
+                sort_options(order.descending),
+            ));
+            columns.push(column.clone());
+        }
+        Ok(RowConverter::new(fields)?.convert_columns(&columns)?)
+    }
+
+    // The select list of the intermediate result, under the output names
+    fn project(&self, batch: &RecordBatch) -> Result<RecordBatch, Box<dyn Error>> {
+        let schema = batch.schema();
+        let mut fields = vec![];
+        let mut columns: Vec<ArrayRef> = vec![];
+        for item in self.items.iter() {
+            if let SelectItem::Wildcard = item {
+                // Hidden ORDER BY columns come after the selected ones
+                let hidden = self.order_names();
+                for (field, column) in schema.fields().iter().zip(batch.columns()) {
+                    if !hidden.contains(field.name()) {
+                        fields.push(field.as_ref().clone());
+                        columns.push(column.clone());
+                    }
+                }
+                continue;
+            }
+            let idx = schema.index_of(&item.source())?;
+            fields.push(Field::new(
+                item.output_name(),
+                batch.column(idx).data_type().clone(),
+                true,
+            ));
+            columns.push(batch.column(idx).clone());
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
+
+// Concatenate batches read from several files, casting to the types of the first one
+// when a column's type differs between files
+fn concat_cast(batches: Vec<RecordBatch>) -> Result<RecordBatch, Box<dyn Error>> {
+    let Some(first) = batches.first() else {
+        return Ok(RecordBatch::new_empty(Arc::new(Schema::empty())));
+    };
+    let schema = first.schema();
+    let cast_batches = batches
+        .iter()
+        .map(|batch| cast_batch(batch, &schema))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(concat_batches(&schema, &cast_batches)?)
+}
+
+// A batch read from one of the files with the columns of the first one
+fn cast_batch(batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch, Box<dyn Error>> {
+    if batch.num_columns() != schema.fields().len() {
+        return Err("The files have different columns".into());
+    }
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .map(|(column, field)| cast(column, field.data_type()))
+        .collect::<Result<Vec<ArrayRef>, _>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+// Aggregate from its result column name, e.g. sum(amount)
+fn parse_aggregate_name(name: &str) -> Option<Aggregate> {
+    let (func, rest) = name.split_once('(')?;
+    let column = rest.strip_suffix(')')?;
+    Some(Aggregate {
+        func: AggFunc::parse(func)?,
+        column: (column != "*").then(|| column.to_string()),
+    })
+}
+
+struct QueryParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // Consume the keyword if it comes next
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found =
+            matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Box<dyn Error>> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("Expected {} in query", keyword.to_ascii_uppercase()).into())
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            Some(token) => Err(format!("Expected a column name, got {:?}", token).into()),
+            None => Err("Expected a column name at the end of query".into()),
+        }
+    }
+
+    fn parse_item(&mut self) -> Result<SelectItem, Box<dyn Error>> {
+        let item = self.parse_expression()?;
+        let alias = if self.keyword("as") {
+            Some(self.parse_name()?)
+        } else {
+            match self.peek() {
+                Some(Token::Ident(word)) if !word.eq_ignore_ascii_case("from") => {
+                    Some(self.parse_name()?)
+                }
+                _ => None,
+            }
+        };
+        Ok(match item {
+            SelectItem::Column { path, .. } => SelectItem::Column { path, alias },
+            SelectItem::Aggregate { aggregate, .. } => SelectItem::Aggregate { aggregate, alias },
+            SelectItem::Wildcard => SelectItem::Wildcard,
+        })
+    }
+
+    // A column, * or an aggregate such as count(*) or sum(amount)
+    fn parse_expression(&mut self) -> Result<SelectItem, Box<dyn Error>> {
+        if self.peek() == Some(&Token::Star) {
+            self.pos += 1;
+            return Ok(SelectItem::Wildcard);
+        }
+        let name = self.parse_name()?;
+        if self.peek() != Some(&Token::LParen) {
+            return Ok(SelectItem::Column {
+                path: name,
+                alias: None,
+            });
+        }
+        let func = AggFunc::parse(&name).ok_or_else(|| format!("Unknown function {}", name))?;
+        self.pos += 1;
+        let column = match self.next() {
+            Some(Token::Star) if func == AggFunc::Count => None,
+            Some(Token::Ident(column)) => Some(column),
+            _ => return Err(format!("Expected a column in {}()", name).into()),
+        };
+        if self.next() != Some(Token::RParen) {
+            return Err(format!("Missing ) after {}(", name).into());
+        }
+        Ok(SelectItem::Aggregate {
+            aggregate: Aggregate { func, column },
+            alias: None,
+        })
+    }
+}
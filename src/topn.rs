@@ -0,0 +1,449 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::error::Error;
+
+use arrow::array::{RecordBatch, UInt32Array};
+use arrow::compute::{SortOptions, cast, interleave_record_batch, take_record_batch};
+use arrow::row::{OwnedRow, RowConverter, SortField};
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use parquet::arrow::async_reader::AsyncFileReader;
+
+use crate::pqt::filter_rows;
+use crate::predicate::{Predicate, column_by_path, statistics_converter};
+
+// Rows kept for the top ones beyond this many are compacted away
+const MIN_COMPACT_ROWS: usize = 8192;
+
+// A column `view --order-by` sorts on
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderBy {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl OrderBy {
+    // Parse `amount desc, id`: columns with an optional asc or desc each
+    pub fn parse_list(value: &str) -> Result<Vec<OrderBy>, String> {
+        let mut order_by = vec![];
+        for part in value.split(',') {
+            let words: Vec<&str> = part.split_whitespace().collect();
+            let descending = match words[..] {
+                [_] => false,
+                [_, direction] if direction.eq_ignore_ascii_case("asc") => false,
+                [_, direction] if direction.eq_ignore_ascii_case("desc") => true,
+                _ => {
+                    return Err(format!(
+                        "Invalid order: {}, expected <column> [asc|desc]",
+                        part.trim()
+                    ));
+                }
+            };
+            order_by.push(OrderBy {
+                column: words[0].to_string(),
+                descending,
+            });
+        }
+        Ok(order_by)
+    }
+
+    fn sort_options(&self) -> SortOptions {
+        sort_options(self.descending)
+    }
+}
+
+// Nulls come last either way, so the top rows are ones with values
+pub(crate) fn sort_options(descending: bool) -> SortOptions {
+    SortOptions {
+        descending,
+        nulls_first: false,
+    }
+}
+
+// A row among the top ones so far: its sort key, its place in the file, which keeps
+// rows with equal keys in file order, and where its values are kept
+struct Candidate {
+    key: OwnedRow,
+    position: (usize, usize),
+    batch: usize,
+    row: usize,
+}
+
+impl Candidate {
+    fn rank(&self) -> (arrow::row::Row<'_>, (usize, usize)) {
+        (self.key.row(), self.position)
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank() == other.rank()
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+// The rows read for the top ones, the worst of those kept on top of the heap
+pub(crate) struct TopRows {
+    count: usize,
+    heap: BinaryHeap<Candidate>,
+    batches: Vec<RecordBatch>,
+    rows: usize,
+}
+
+impl TopRows {
+    pub(crate) fn new(count: usize) -> Self {
+        Self {
+            count,
+            heap: BinaryHeap::new(),
+            batches: vec![],
+            rows: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.heap.len() >= self.count
+    }
+
+    // Keep the rows of the batch that rank among the top ones so far
+    pub(crate) fn push(
+        &mut self,
+        batch: &RecordBatch,
+        keys: &arrow::row::Rows,
+        row_group: usize,
+        first_row: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut kept = vec![];
+        for i in 0..batch.num_rows() {
+            let position = (row_group, first_row + i);
+            if self.is_full()
+                && let Some(worst) = self.heap.peek()
+                && (keys.row(i), position) >= worst.rank()
+            {
+                continue;
+            }
+            self.heap.push(Candidate {
+                key: keys.row(i).owned(),
+                position,
+                batch: self.batches.len(),
+                row: kept.len(),
+            });
+            kept.push(i as u32);
+            if self.heap.len() > self.count {
+                self.heap.pop();
+            }
+        }
+        if !kept.is_empty() {
+            self.rows += kept.len();
+            self.batches
+                .push(take_record_batch(batch, &UInt32Array::from(kept))?);
+        }
+        // Most of the rows kept may have dropped out of the top since
+        if self.rows > 2 * self.count.max(MIN_COMPACT_ROWS) {
+            let candidates = std::mem::take(&mut self.heap).into_vec();
+            self.compact(candidates)?;
+        }
+        Ok(())
+    }
+
+    // Keep only the values of the candidates, in one batch in their order
+    fn compact(&mut self, mut candidates: Vec<Candidate>) -> Result<(), Box<dyn Error>> {
+        let indices: Vec<(usize, usize)> = candidates.iter().map(|c| (c.batch, c.row)).collect();
+        let batches: Vec<&RecordBatch> = self.batches.iter().collect();
+        let batch = interleave_record_batch(&batches, &indices)?;
+        for (i, candidate) in candidates.iter_mut().enumerate() {
+            candidate.batch = 0;
+            candidate.row = i;
+        }
+        self.rows = batch.num_rows();
+        self.batches = vec![batch];
+        self.heap = BinaryHeap::from(candidates);
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<Option<RecordBatch>, Box<dyn Error>> {
+        if self.heap.is_empty() {
+            return Ok(None);
+        }
+        let candidates = std::mem::take(&mut self.heap).into_sorted_vec();
+        self.compact(candidates)?;
+        Ok(self.batches.pop())
+    }
+}
+
+// The first `count` rows of the given row groups in the order of `order_by`, keeping
+// only as many rows as that in memory. Row groups are read best first by the
+// statistics of the first order column, and reading stops at the first whose
+// statistics prove none of its rows can make it into the top.
+pub(crate) async fn top_rows<T: AsyncFileReader + Unpin + Send + 'static>(
+    builder: ParquetRecordBatchStreamBuilder<T>,
+    order_by: &[OrderBy],
+    row_groups: &[usize],
+    filter: Option<&Predicate>,
+    count: usize,
+) -> Result<Vec<RecordBatch>, Box<dyn Error>> {
+    let metadata = builder.metadata().clone();
+    let arrow_schema = builder.schema().clone();
+    let schema_descr = builder.parquet_schema().clone();
+
+    // The best value each row group may hold in the first order column, as a sort key
+    // comparable to the keys of rows
+    let first = &order_by[0];
+    let mut bounds = None;
+    // Statistics leave NaN out, which sorts beyond every number, so they don't bound
+    // floating point columns
+    let floating = arrow_schema
+        .field_with_name(&first.column)
+        .is_ok_and(|field| field.data_type().is_floating());
+    if !floating
+        && let Some(converter) = statistics_converter(&first.column, &arrow_schema, &schema_descr)?
+    {
+        let values = if first.descending {
+            converter.row_group_maxes(metadata.row_groups())?
+        } else {
+            converter.row_group_mins(metadata.row_groups())?
+        };
+        let data_type = arrow_schema.field_with_name(&first.column)?.data_type();
+        let values = if values.data_type() == data_type {
+            values
+        } else {
+            cast(&values, data_type)?
+        };
+        let converter = RowConverter::new(vec![SortField::new_with_options(
+            data_type.clone(),
+            first.sort_options(),
+        )])?;
+        let keys = converter.convert_columns(std::slice::from_ref(&values))?;
+        bounds = Some((converter, keys, values));
+    }
+
+    // Row groups without statistics come first, as they can't be skipped
+    let mut order = row_groups.to_vec();
+    if let Some((_, keys, values)) = &bounds {
+        order.sort_by(|a, b| {
+            (values.is_valid(*a), keys.row(*a)).cmp(&(values.is_valid(*b), keys.row(*b)))
+        });
+    }
+    let (order, builder) = match filter {
+        Some(filter) => {
+            let keep = filter.prune_row_groups(&metadata, &arrow_schema)?;
+            let order: Vec<usize> = order.into_iter().filter(|rg| keep[*rg]).collect();
+            let builder = filter_rows(builder.with_row_groups(order.clone()), filter, &order)?;
+            (order, builder)
+        }
+        None => (order.clone(), builder.with_row_groups(order)),
+    };
+    let mut stream = builder.with_batch_size(8192).build()?;
+
+    let mut fields = vec![];
+    for column in order_by {
+        let field = column_by_path(
+            &RecordBatch::new_empty(stream.schema().clone()),
+            &column.column,
+        )?;
+        fields.push(SortField::new_with_options(
+            field.data_type().clone(),
+            column.sort_options(),
+        ));
+    }
+    let converter = RowConverter::new(fields)?;
+    let mut top = TopRows::new(count);
+    let mut read = 0;
+    for rg in order.iter() {
+        // The worst of the top rows so far beats everything the row group may hold
+        if top.is_full()
+            && let Some((first_converter, keys, values)) = &bounds
+            && values.is_valid(*rg)
+            && let Some(worst) = top.heap.peek()
+        {
+            let worst_batch = &top.batches[worst.batch];
+            let worst_value = column_by_path(worst_batch, &first.column)?.slice(worst.row, 1);
+            let worst_key = first_converter.convert_columns(&[worst_value])?;
+            if keys.row(*rg) > worst_key.row(0) {
+                break;
+            }
+        }
+        let Some(reader) = stream.next_row_group().await? else {
+            break;
+        };
+        read += 1;
+        let mut first_row = 0;
+        for batch in reader {
+            let batch = batch?;
+            let columns = order_by
+                .iter()
+                .map(|o| column_by_path(&batch, &o.column))
+                .collect::<Result<Vec<_>, _>>()?;
+            let keys = converter.convert_columns(&columns)?;
+            top.push(&batch, &keys, *rg, first_row)?;
+            first_row += batch.num_rows();
+        }
+    }
+    println!(
+        "Skipped {} of {} row groups using statistics of {}{}",
+        row_groups.len() - read,
+        row_groups.len(),
+        if filter.is_some() {
+            "the filter and "
+        } else {
+            ""
+        },
+        first.column
+    );
+    Ok(top.finish()?.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{AsArray, Int64Array};
+    use arrow::datatypes::{DataType, Field, Int64Type, Schema};
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+
+    use super::*;
+    use crate::pqt::reader_from_bytes;
+    use crate::utils::SplitMix64;
+
+    fn batch(ids: Vec<i64>, values: Vec<Option<i64>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("v", DataType::Int64, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(ids)),
+                Arc::new(Int64Array::from(values)),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn keys(batch: &RecordBatch, descending: bool) -> arrow::row::Rows {
+        RowConverter::new(vec![SortField::new_with_options(
+            DataType::Int64,
+            sort_options(descending),
+        )])
+        .unwrap()
+        .convert_columns(&[batch.column(1).clone()])
+        .unwrap()
+    }
+
+    fn ids(batches: &[RecordBatch]) -> Vec<i64> {
+        batches
+            .iter()
+            .flat_map(|b| b.column(0).as_primitive::<Int64Type>().values().to_vec())
+            .collect()
+    }
+
+    // Random values, every tenth null
+    fn values(rows: usize, seed: u64) -> Vec<Option<i64>> {
+        let mut random = SplitMix64::new(seed);
+        (0..rows)
+            .map(|i| (i % 10 != 3).then(|| random.below(1000) as i64))
+            .collect()
+    }
+
+    // The ids of the first `count` rows by value, nulls last and equal values in
+    // file order
+    fn sorted_ids(values: &[Option<i64>], descending: bool, count: usize) -> Vec<i64> {
+        let mut ids: Vec<usize> = (0..values.len()).collect();
+        ids.sort_by_key(|i| {
+            let value = values[*i].map(|v| if descending { -v } else { v });
+            (value.is_none(), value, *i)
+        });
+        ids.into_iter().take(count).map(|i| i as i64).collect()
+    }
+
+    #[test]
+    fn compaction_keeps_the_top_rows() {
+        let values = values(50_000, 1);
+        for descending in [false, true] {
+            let mut top = TopRows::new(100);
+            for (n, chunk) in values.chunks(1000).enumerate() {
+                let first = n as i64 * 1000;
+                let batch = batch((first..first + 1000).collect(), chunk.to_vec());
+                top.push(&batch, &keys(&batch, descending), 0, first as usize)
+                    .unwrap();
+                // compacted whenever the rows kept reach twice the minimum
+                assert!(top.rows <= 2 * MIN_COMPACT_ROWS + 1000);
+            }
+            let result = top.finish().unwrap().unwrap();
+            assert_eq!(ids(&[result]), sorted_ids(&values, descending, 100));
+        }
+    }
+
+    #[test]
+    fn nulls_come_last_both_ways() {
+        let batch = batch(
+            (0..6).collect(),
+            vec![Some(3), None, Some(1), None, Some(2), Some(1)],
+        );
+        for (descending, expected) in [(false, [2, 5, 4, 0, 1]), (true, [0, 4, 2, 5, 1])] {
+            let mut top = TopRows::new(5);
+            top.push(&batch, &keys(&batch, descending), 0, 0).unwrap();
+            assert_eq!(ids(&[top.finish().unwrap().unwrap()]), expected);
+        }
+    }
+
+    // Row groups of 100 rows whose values drift up, so statistics let most be skipped
+    fn file(values: &[Option<i64>]) -> Vec<u8> {
+        let batch = batch((0..values.len() as i64).collect(), values.to_vec());
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(100)
+            .build();
+        let mut content = vec![];
+        let mut writer =
+            ArrowWriter::try_new(&mut content, batch.schema(), Some(properties)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        content
+    }
+
+    #[tokio::test]
+    async fn statistics_never_skip_top_rows() {
+        let values: Vec<Option<i64>> = values(2000, 2)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| v.map(|v| v + i as i64 / 2))
+            .collect();
+        let content = file(&values);
+        let row_groups: Vec<usize> = (0..20).collect();
+        for (filter, first_id) in [(None, 0), (Some("id >= 500"), 500)] {
+            let filter = filter.map(|f| Predicate::parse(f).unwrap());
+            for descending in [false, true] {
+                for count in [1, 25, 150] {
+                    let builder =
+                        ParquetRecordBatchStreamBuilder::new(reader_from_bytes(content.clone()))
+                            .await
+                            .unwrap();
+                    let order_by = [OrderBy {
+                        column: "v".to_string(),
+                        descending,
+                    }];
+                    let top = top_rows(builder, &order_by, &row_groups, filter.as_ref(), count)
+                        .await
+                        .unwrap();
+                    let expected: Vec<i64> = sorted_ids(&values[first_id..], descending, count)
+                        .into_iter()
+                        .map(|id| id + first_id as i64)
+                        .collect();
+                    assert_eq!(ids(&top), expected);
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,57 @@
-pub fn compute_col_max_len(rows: &Vec<Vec<String>>) -> Vec<usize> {
+use unicode_width::UnicodeWidthStr;
+
+// Split a console line into arguments. Double quotes group words and are removed,
+// single quotes group words but are kept, so `--where "name = 'a b'"` stays intact.
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => {
+                quote = None;
+                if c == '\'' {
+                    current.push(c);
+                }
+            }
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_arg = true;
+                if c == '\'' {
+                    current.push(c);
+                }
+            }
+            None if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            None => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+// Columns a value takes on the terminal: wide characters such as CJK take two,
+// combining marks none
+pub fn display_width(what: &str) -> usize {
+    UnicodeWidthStr::width(what)
+}
+
+pub fn compute_col_max_len(rows: &[Vec<String>]) -> Vec<usize> {
     let mut col_max_len: Vec<usize> = Vec::new();
     for row in rows.iter() {
         for (colno, column) in row.iter().enumerate() {
-            let strlen = column.len();
+            let strlen = display_width(column);
             if col_max_len.len() <= colno {
                 col_max_len.push(strlen);
             } else {
@@ -15,7 +64,21 @@ pub fn compute_col_max_len(rows: &Vec<Vec<String>>) -> Vec<usize> {
     col_max_len
 }
 
-pub fn print_rows(rows: &Vec<Vec<String>>, col_max_len: &Vec<usize>, header: bool) {
+pub fn print_rows(rows: &[Vec<String>], col_max_len: &[usize], header: bool) {
+    print_rows_with(rows, col_max_len, header, print_with_spaces);
+}
+
+// Same as print_rows, but cells are aligned to the left, e.g. to keep tree indentation
+pub fn print_rows_left(rows: &[Vec<String>], col_max_len: &[usize], header: bool) {
+    print_rows_with(rows, col_max_len, header, print_left_aligned);
+}
+
+fn print_rows_with(
+    rows: &[Vec<String>],
+    col_max_len: &[usize],
+    header: bool,
+    print_cell: fn(&str, usize),
+) {
     let mut total = 0;
     for l in col_max_len.iter() {
         total += l;
@@ -24,7 +87,7 @@ pub fn print_rows(rows: &Vec<Vec<String>>, col_max_len: &Vec<usize>, header: boo
     for (rowno, row) in rows.iter().enumerate() {
         for (colno, column) in row.iter().enumerate() {
             let spacing = col_max_len[colno];
-            print_with_spaces(&column, spacing);
+            print_cell(column, spacing);
             print!(" | ");
         }
         if header && rowno == 0 {
@@ -35,6 +98,79 @@ pub fn print_rows(rows: &Vec<Vec<String>>, col_max_len: &Vec<usize>, header: boo
     }
 }
 
+// Columns a table printed by print_rows takes on a line of the terminal
+pub fn table_width(col_max_len: &[usize]) -> usize {
+    col_max_len.iter().map(|l| l + 3).sum()
+}
+
+// Print each row as its own block of `column | value` lines, like psql's \x mode,
+// so wide rows stay readable. The first row holds the column names.
+pub fn print_records(rows: &[Vec<String>], first_record: usize) {
+    let Some((header, records)) = rows.split_first() else {
+        return;
+    };
+    let name_len = header
+        .iter()
+        .map(|name| display_width(name))
+        .max()
+        .unwrap_or(0);
+    let value_len = records
+        .iter()
+        .flatten()
+        .map(|value| display_width(value))
+        .max()
+        .unwrap_or(0);
+    for (recno, record) in records.iter().enumerate() {
+        let title = format!("-[ RECORD {} ]", first_record + recno);
+        let line_len = (name_len + 3 + value_len).max(title.len() + 1);
+        print!("{title}");
+        print_times("-", line_len - title.len());
+        println!();
+        for (name, value) in header.iter().zip(record) {
+            print_left_aligned(name, name_len);
+            println!(" | {value}");
+        }
+    }
+}
+
+// Small deterministic pseudo random generator (SplitMix64), good enough for sampling
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform number in 0..n
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+// Byte count in a human readable unit, e.g. 1.5 MiB
+pub fn human_size(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 fn print_times(what: &str, reepat: usize) {
     for _ in 0..reepat {
         print!("{what}");
@@ -42,22 +178,15 @@ fn print_times(what: &str, reepat: usize) {
 }
 
 fn print_with_spaces(what: &str, max_len: usize) {
-    let strlen = what.len();
-    let mut prntstr = what;
+    let mut len_remains = max_len.saturating_sub(display_width(what));
+    let l2 = len_remains >> 1;
+    print_times(" ", l2);
+    print!("{what}");
+    len_remains -= l2;
+    print_times(" ", len_remains);
+}
 
-    if strlen > max_len {
-        prntstr = what.get(0..max_len).unwrap();
-        print!("{prntstr}");
-    } else {
-        let mut len_remains = max_len - strlen;
-        let l2 = len_remains >> 1;
-        for _ in 0..l2 {
-            print!(" ");
-        }
-        print!("{prntstr}");
-        len_remains -= l2;
-        for _ in 0..len_remains {
-            print!(" ");
-        }
-    }
+fn print_left_aligned(what: &str, max_len: usize) {
+    print!("{what}");
+    print_times(" ", max_len.saturating_sub(display_width(what)));
 }
@@ -0,0 +1,347 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::column::page::{Page, PageMetadata, PageReader};
+use parquet::column::reader::{ColumnReader, ColumnReaderImpl, get_column_reader};
+use parquet::data_type::DataType;
+use parquet::errors::ParquetError;
+use parquet::file::serialized_reader::SerializedPageReader;
+
+use crate::pages::{ChunkBytes, ChunkPage, page_headers};
+use crate::pqt::read_metadata;
+use crate::utils::{compute_col_max_len, print_rows_left};
+
+// Rows decoded at a time when checking a column chunk
+const VERIFY_BATCH_SIZE: usize = 8192;
+
+// Something wrong with a file, located as closely as it could be
+pub struct Problem {
+    pub row_group: Option<usize>,
+    pub column: Option<String>,
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+// What verifying a file went through and found
+#[derive(Default)]
+pub struct Verification {
+    pub row_groups: usize,
+    pub column_chunks: usize,
+    pub pages: usize,
+    // pages carrying a checksum, all of which were checked
+    pub checksums: usize,
+    pub rows: i64,
+    // column chunks of encrypted columns, which are only checked for their layout
+    pub encrypted: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl Verification {
+    fn problem(
+        &mut self,
+        row_group: Option<usize>,
+        column: Option<&str>,
+        offset: Option<u64>,
+        message: impl ToString,
+    ) {
+        self.problems.push(Problem {
+            row_group,
+            column: column.map(String::from),
+            offset,
+            message: message.to_string(),
+        });
+    }
+
+    // One line summing the file up, e.g. `OK, 10 row groups, 60 column chunks, ...`
+    pub fn summary(&self) -> String {
+        let mut summary = match self.problems.len() {
+            0 => "OK".to_string(),
+            1 => "1 problem".to_string(),
+            n => format!("{} problems", n),
+        };
+        summary += &format!(
+            ", {} row groups, {} column chunks, {} pages ({} with checksums), {} rows",
+            self.row_groups, self.column_chunks, self.pages, self.checksums, self.rows
+        );
+        if self.encrypted > 0 {
+            summary += &format!(", {} encrypted column chunks not decoded", self.encrypted);
+        }
+        summary
+    }
+
+    pub fn print_problems(&self) {
+        if self.problems.is_empty() {
+            return;
+        }
+        let mut rows = vec![vec![
+            "Row group".to_string(),
+            "Column".to_string(),
+            "Offset".to_string(),
+            "Problem".to_string(),
+        ]];
+        for problem in self.problems.iter() {
+            rows.push(vec![
+                problem
+                    .row_group
+                    .map(|rg| rg.to_string())
+                    .unwrap_or_default(),
+                problem.column.clone().unwrap_or_default(),
+                problem.offset.map(|o| o.to_string()).unwrap_or_default(),
+                problem.message.clone(),
+            ]);
+        }
+        let col_max_len = compute_col_max_len(&rows);
+        print_rows_left(&rows, &col_max_len, true);
+    }
+}
+
+// Check a whole file: the magic bytes, that the footer decodes and its row counts add
+// up, that column chunks don't overlap, and that every page of every column chunk
+// decompresses, matches its checksum and decodes to as many rows as the row group has.
+// Problems are collected rather than failing on the first one.
+pub async fn parquet_verify<T: AsyncFileReader>(mut reader: T) -> Verification {
+    let mut verification = Verification::default();
+
+    match reader.get_bytes(0..4).await {
+        // Files with an encrypted footer start with PARE
+        Ok(magic) if magic.as_ref() == b"PAR1" || magic.as_ref() == b"PARE" => {}
+        Ok(_) => verification.problem(None, None, Some(0), "File doesn't start with PAR1"),
+        Err(e) => verification.problem(None, None, Some(0), e),
+    }
+    // Reading the footer checks the magic bytes and the footer length at the end
+    let metadata = match read_metadata(&mut reader, false).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            verification.problem(None, None, None, format!("Footer: {}", e));
+            return verification;
+        }
+    };
+    let file_metadata = metadata.file_metadata();
+    let schema_descr = file_metadata.schema_descr();
+    verification.row_groups = metadata.num_row_groups();
+    verification.rows = metadata.row_groups().iter().map(|rg| rg.num_rows()).sum();
+    if verification.rows != file_metadata.num_rows() {
+        verification.problem(
+            None,
+            None,
+            None,
+            format!(
+                "Row groups have {} rows, the footer says {}",
+                verification.rows,
+                file_metadata.num_rows()
+            ),
+        );
+    }
+
+    let mut ranges = vec![];
+    for (idx, rg) in metadata.row_groups().iter().enumerate() {
+        for (column_idx, chunk) in rg.columns().iter().enumerate() {
+            let column = chunk.column_path().string();
+            let (start, length) = chunk.byte_range();
+            verification.column_chunks += 1;
+            ranges.push((start, start + length, idx, column.clone()));
+            if chunk.crypto_metadata().is_some() {
+                verification.encrypted += 1;
+                continue;
+            }
+            let data = match reader.get_bytes(start..start + length).await {
+                Ok(data) => data,
+                Err(e) => {
+                    verification.problem(Some(idx), Some(&column), Some(start), e);
+                    continue;
+                }
+            };
+            let pages = match page_headers(start, &data) {
+                Ok(pages) => pages,
+                Err(e) => {
+                    verification.problem(Some(idx), Some(&column), None, e);
+                    continue;
+                }
+            };
+            verification.pages += pages.len();
+            verification.checksums += pages.iter().filter(|p| p.header.crc.is_some()).count();
+            if let Some(last) = pages.last() {
+                let end =
+                    last.offset + (last.header_size as i64 + last.header.compressed_size) as u64;
+                if end != start + length {
+                    verification.problem(
+                        Some(idx),
+                        Some(&column),
+                        Some(last.offset),
+                        format!(
+                            "The last page ends at {}, the column chunk at {}",
+                            end,
+                            start + length
+                        ),
+                    );
+                    continue;
+                }
+            }
+
+            let decoded = Arc::new(AtomicUsize::new(0));
+            let pages_decoded = decoded.clone();
+            let chunk_metadata = chunk.clone();
+            let num_rows = rg.num_rows() as usize;
+            let descr = schema_descr.column(column_idx);
+            let result = without_panics(move || {
+                let page_reader = SerializedPageReader::new(
+                    Arc::new(ChunkBytes { start, data }),
+                    &chunk_metadata,
+                    num_rows,
+                    None,
+                )?;
+                let page_reader = CountingPageReader {
+                    inner: page_reader,
+                    pages: pages_decoded,
+                };
+                match get_column_reader(descr, Box::new(page_reader)) {
+                    ColumnReader::BoolColumnReader(r) => read_column(r),
+                    ColumnReader::Int32ColumnReader(r) => read_column(r),
+                    ColumnReader::Int64ColumnReader(r) => read_column(r),
+                    ColumnReader::Int96ColumnReader(r) => read_column(r),
+                    ColumnReader::FloatColumnReader(r) => read_column(r),
+                    ColumnReader::DoubleColumnReader(r) => read_column(r),
+                    ColumnReader::ByteArrayColumnReader(r) => read_column(r),
+                    ColumnReader::FixedLenByteArrayColumnReader(r) => read_column(r),
+                }
+            })
+            .await;
+            match result {
+                Ok((records, levels)) => {
+                    if records as i64 != rg.num_rows() {
+                        verification.problem(
+                            Some(idx),
+                            Some(&column),
+                            Some(start),
+                            format!(
+                                "{} rows decoded, the row group has {}",
+                                records,
+                                rg.num_rows()
+                            ),
+                        );
+                    }
+                    if levels as i64 != chunk.num_values() {
+                        verification.problem(
+                            Some(idx),
+                            Some(&column),
+                            Some(start),
+                            format!(
+                                "{} values decoded, the column chunk has {}",
+                                levels,
+                                chunk.num_values()
+                            ),
+                        );
+                    }
+                }
+                Err(e) => {
+                    // The page being decoded when it failed, the last one handed out
+                    let page = decoded.load(Ordering::Relaxed).max(1) - 1;
+                    let offset = pages.get(page).map(|p: &ChunkPage| p.offset);
+                    verification.problem(Some(idx), Some(&column), offset, e);
+                }
+            }
+        }
+    }
+
+    // Column chunks must lie after the leading magic bytes and not overlap
+    ranges.sort();
+    let mut end = 4;
+    for (start, chunk_end, idx, column) in ranges {
+        if start < end {
+            verification.problem(
+                Some(idx),
+                Some(&column),
+                Some(start),
+                format!("Column chunk overlaps bytes before {}", end),
+            );
+        }
+        end = end.max(chunk_end);
+    }
+    verification
+}
+
+// Some corrupt data makes the decoders panic rather than fail, count that as an error
+// too. Decoding runs on a blocking task, so a panic ends only that task and the
+// decoder state it leaves behind goes with it.
+async fn without_panics<R: Send + 'static>(
+    f: impl FnOnce() -> parquet::errors::Result<R> + Send + 'static,
+) -> parquet::errors::Result<R> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => {
+            let payload = e.into_panic();
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Err(ParquetError::General(format!(
+                "Decoding failed: {}",
+                message
+            )))
+        }
+        Err(e) => Err(ParquetError::General(e.to_string())),
+    }
+}
+
+// Decode all values of a column chunk, returning the number of rows and of values
+// including nulls
+fn read_column<T: DataType>(
+    mut reader: ColumnReaderImpl<T>,
+) -> parquet::errors::Result<(usize, usize)> {
+    let (mut def_levels, mut rep_levels, mut values) = (vec![], vec![], vec![]);
+    let (mut rows, mut levels) = (0, 0);
+    loop {
+        def_levels.clear();
+        rep_levels.clear();
+        values.clear();
+        let (records_read, values_read, levels_read) = reader.read_records(
+            VERIFY_BATCH_SIZE,
+            Some(&mut def_levels),
+            Some(&mut rep_levels),
+            &mut values,
+        )?;
+        if records_read == 0 && levels_read == 0 {
+            return Ok((rows, levels));
+        }
+        rows += records_read;
+        // Columns without levels report the values read
+        levels += levels_read.max(values_read);
+    }
+}
+
+// Hands out the pages of a column chunk, counting them so a decoding error can be
+// traced to the page it happened in
+struct CountingPageReader<R: PageReader> {
+    inner: R,
+    pages: Arc<AtomicUsize>,
+}
+
+impl<R: PageReader> Iterator for CountingPageReader<R> {
+    type Item = parquet::errors::Result<Page>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.get_next_page().transpose()
+    }
+}
+
+impl<R: PageReader> PageReader for CountingPageReader<R> {
+    fn get_next_page(&mut self) -> parquet::errors::Result<Option<Page>> {
+        self.pages.fetch_add(1, Ordering::Relaxed);
+        self.inner.get_next_page()
+    }
+
+    fn peek_next_page(&mut self) -> parquet::errors::Result<Option<PageMetadata>> {
+        self.inner.peek_next_page()
+    }
+
+    fn skip_next_page(&mut self) -> parquet::errors::Result<()> {
+        self.pages.fetch_add(1, Ordering::Relaxed);
+        self.inner.skip_next_page()
+    }
+
+    fn at_record_boundary(&mut self) -> parquet::errors::Result<bool> {
+        self.inner.at_record_boundary()
+    }
+}
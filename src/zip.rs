@@ -41,7 +41,7 @@ pub async fn zip_list_http(
             println!("Detected ZIP64 format, EOCD64 at offset {}", eocd64_offset);
             let local_offset = eocd64_offset - eocd_start;
             println!("Adjusted EOCD64 offset in buffer: {}", local_offset);
-            handle_zip64(client, url, &eocd_bytes.to_vec(), local_offset).await
+            handle_zip64(client, url, &eocd_bytes, local_offset).await
         }
         ZipFormat::Regular(cd_offset, cd_size) => {
             println!("Detected regular ZIP format");
@@ -120,7 +120,7 @@ fn check_zip_format(cursor: &mut std::io::Cursor<Vec<u8>>) -> ZipFormat {
 async fn handle_zip64(
     client: &Client,
     url: &str,
-    bytes: &Vec<u8>,
+    bytes: &[u8],
     local_offset: u64,
 ) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
     use byteorder::{LittleEndian, ReadBytesExt};